@@ -1,6 +1,13 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize as De, Serialize as Ser};
 
-use crate::git::Branch;
+use super::{Blocked, Freeze, Window};
+use crate::{extra::StrExtra, git::Branch};
+
+/// How long a single hook command may run if `hook_timeout_secs` isn't set
+const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 300;
 
 /// A branch diff that, when merged, triggers a deploy
 #[derive(PartialEq, Clone, Debug, Ser, De)]
@@ -14,6 +21,34 @@ pub struct Mergeable {
   pub target: Branch,
   /// Users who can initiate or approve deploys
   pub users: Vec<User>,
+  /// Shell commands run in the repo before merging, with `base` checked out.
+  /// If any exits not OK, the deploy fails.
+  #[serde(default)]
+  pub pre_merge: Vec<String>,
+  /// Shell commands run in the repo after merging, with `target` checked out.
+  /// These run before pushing, so any commits they make are pushed along with the merge.
+  #[serde(default)]
+  pub post_merge: Vec<String>,
+  /// Seconds a single hook command may run before it's killed. Defaults to 5 minutes.
+  #[serde(default)]
+  pub hook_timeout_secs: Option<u64>,
+  /// When deploys to this environment are allowed.
+  /// If empty, deploys are allowed any time that isn't frozen.
+  #[serde(default)]
+  pub windows: Vec<Window>,
+  /// Periods of time during which deploys to this environment are not allowed
+  #[serde(default)]
+  pub freezes: Vec<Freeze>,
+  /// While a deploy to this environment is waiting for approval,
+  /// remind outstanding approvers in its thread every this many minutes
+  #[serde(default)]
+  pub reminder_interval_mins: Option<u64>,
+  /// Channel to ask for approval of deploys to this environment in, instead of the app's `notification_channel_id`
+  #[serde(default)]
+  pub notification_channel_id: Option<String>,
+  /// Channel to announce finished deploys to this environment in, as well as in the request's thread
+  #[serde(default)]
+  pub announcement_channel_id: Option<String>,
 }
 
 impl Mergeable {
@@ -21,6 +56,22 @@ impl Mergeable {
   pub fn name_eq(&self, name: impl AsRef<str>) -> bool {
     self.name.trim().to_lowercase() == name.as_ref().trim().to_lowercase()
   }
+
+  /// How long a single pre- or post-merge hook command may run
+  pub fn hook_timeout(&self) -> Duration {
+    Duration::from_secs(self.hook_timeout_secs.unwrap_or(DEFAULT_HOOK_TIMEOUT_SECS))
+  }
+
+  /// Check that this environment isn't frozen and that a deploy window is open at a point in time
+  pub fn allowed_at(&self, at: DateTime<Utc>) -> Result<(), Blocked> {
+    if let Some(freeze) = self.freezes.iter().find(|f| f.contains(at)) {
+      Err(Blocked::Frozen(self.name.clone(), freeze.clone()))
+    } else if !self.windows.is_empty() && !self.windows.iter().any(|w| w.contains(at)) {
+      Err(Blocked::OutsideWindows(self.name.clone(), self.windows.clone()))
+    } else {
+      Ok(())
+    }
+  }
 }
 
 /// A git repository, containing a branch for each environment
@@ -36,6 +87,55 @@ pub struct Repo {
   pub environments: Vec<Mergeable>,
 }
 
+/// An HTTP endpoint that's POSTed every event for an app's deploy jobs
+#[derive(PartialEq, Clone, Debug, Ser, De)]
+pub struct Webhook {
+  /// URL to POST events to
+  pub url: String,
+  /// Key used to sign events, so the endpoint can check they came from us
+  pub secret: String,
+}
+
+/// Chat service an app's deploys are requested & approved in
+#[derive(PartialEq, Clone, Copy, Debug, Default, Ser, De)]
+#[serde(rename_all = "snake_case")]
+pub enum Chat {
+  /// Slack
+  #[default]
+  Slack,
+  /// Mattermost. Only individual users can approve; apps with groups in their environments fail to load.
+  Mattermost,
+}
+
+/// Somewhere besides an app's notification channel to tell about its deploys.
+///
+/// Sinks are sent a summary when a deploy is requested, approved, scheduled, cancelled, finished or gives up,
+/// but not individual approvals or failures that will be retried; those only go to the notification channel.
+#[derive(PartialEq, Clone, Debug, Ser, De)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Sink {
+  /// Another channel in the app's slack workspace
+  Slack {
+    /// Slack ID of the channel
+    channel_id: String,
+  },
+  /// Email, sent through the SMTP server configured with `SMTP_*` env vars
+  Email {
+    /// Addresses to send to
+    to: Vec<String>,
+  },
+  /// A Microsoft Teams incoming webhook
+  Teams {
+    /// URL of the webhook
+    url: String,
+  },
+  /// A Mattermost incoming webhook
+  Mattermost {
+    /// URL of the webhook
+    url: String,
+  },
+}
+
 /// A user who can initiate or will be asked to approve
 #[derive(PartialEq, Clone, Debug, Ser, De)]
 #[serde(untagged)]
@@ -96,14 +196,44 @@ pub struct App {
   /// Must not contain spaces.
   pub name: String,
 
-  /// Slack workspace ID that `/deploy` is allowed in
+  /// Slack workspace ID (or mattermost team ID) that `/deploy` is allowed in
   pub team_id: String,
 
-  /// Slack channel to send notifications to
+  /// Chat service the app's deploys are requested & approved in
+  #[serde(default)]
+  pub chat: Chat,
+
+  /// Slack (or mattermost) channel to send notifications to
   pub notification_channel_id: String,
 
   /// Repositories that will be
   pub repos: Vec<Repo>,
+
+  /// Names of environments in the order changes should be deployed to them (e.g. `["qa", "staging", "prod"]`).
+  /// An environment in the chain can only be deployed with what was last deployed to the one before it.
+  #[serde(default)]
+  pub promotion: Vec<String>,
+
+  /// DM the members of approver groups when a deploy needs approval, not only individual approvers
+  #[serde(default)]
+  pub dm_groups: bool,
+
+  /// If set, DM approvers again if a deploy still needs their approval after this many minutes
+  #[serde(default)]
+  pub dm_reminder_mins: Option<u64>,
+
+  /// Endpoints to notify of every job event.
+  ///
+  /// Not serialized, so that their secrets don't end up in the jobs API or the job store.
+  #[serde(default, skip_serializing)]
+  pub webhooks: Vec<Webhook>,
+
+  /// Other places to send messages about the app's deploys to, besides `notification_channel_id`.
+  ///
+  /// These are secondary: they only get some events (see `Sink`), and the notification channel is still required.
+  /// Not serialized, since their URLs are as good as credentials.
+  #[serde(default, skip_serializing)]
+  pub sinks: Vec<Sink>,
 }
 
 impl App {
@@ -116,6 +246,65 @@ impl App {
         .flat_map(|env| env.users.clone())
         .collect::<Vec<_>>()
   }
+
+  /// Check that an environment may be deployed at a point in time in all repos
+  pub fn allowed_at(&self, env_name: &str, at: DateTime<Utc>) -> Result<(), Blocked> {
+    self.repos
+        .iter()
+        .flat_map(|r| r.environments.iter().filter(|env| env.name_eq(env_name)))
+        .try_for_each(|env| env.allowed_at(at))
+  }
+
+  /// How often to remind approvers of a deploy to an environment waiting for approval.
+  /// If repos disagree, the most frequent wins.
+  pub fn reminder_interval(&self, env_name: &str) -> Option<Duration> {
+    self.repos
+        .iter()
+        .flat_map(|r| r.environments.iter().filter(|env| env.name_eq(env_name)))
+        .filter_map(|env| env.reminder_interval_mins)
+        .min()
+        .map(|mins| Duration::from_secs(mins * 60))
+  }
+
+  /// Channel to send notifications about deploys to an environment to.
+  /// Repos can override the app's channel per environment, and `validate` makes sure they agree.
+  pub fn notification_channel(&self, env_name: &str) -> &str {
+    self.repos
+        .iter()
+        .flat_map(|r| r.environments.iter().filter(|env| env.name_eq(env_name)))
+        .find_map(|env| env.notification_channel_id.as_deref())
+        .unwrap_or(&self.notification_channel_id)
+  }
+
+  /// Channel to announce finished deploys to an environment in, if any repo asks for one
+  pub fn announcement_channel(&self, env_name: &str) -> Option<&str> {
+    self.repos
+        .iter()
+        .flat_map(|r| r.environments.iter().filter(|env| env.name_eq(env_name)))
+        .find_map(|env| env.announcement_channel_id.as_deref())
+  }
+
+  /// Get the stage before an environment in the promotion chain
+  pub fn prev_stage(&self, env_name: &str) -> Option<&str> {
+    self.promotion
+        .iter()
+        .position(|stage| stage.loose_eq(env_name))
+        .and_then(|ix| ix.checked_sub(1))
+        .and_then(|ix| self.promotion.get(ix))
+        .map(String::as_str)
+  }
+
+  /// Get the stage after the one most recently deployed,
+  /// or the first stage if none have been deployed
+  pub fn next_stage(&self, deployed: Option<&str>) -> Option<&str> {
+    match deployed {
+      | Some(env_name) => self.promotion
+                              .iter()
+                              .position(|stage| stage.loose_eq(env_name))
+                              .and_then(|ix| self.promotion.get(ix + 1)),
+      | None => self.promotion.first(),
+    }.map(String::as_str)
+  }
 }
 
 /// Errors encounterable while trying to read `deployables.json`
@@ -125,6 +314,66 @@ pub enum ReadError {
   Io(std::io::Error),
   /// File exists but is not valid json
   Json(serde_json::Error),
+  /// App (named) is requested in mattermost but has group approvers, which mattermost doesn't support
+  MattermostGroups(String),
+  /// App (named) has a deploy window that can't be used, and why
+  InvalidWindow(String, String),
+  /// App (named) has repos that override an environment's (named) channels with different ones
+  ConflictingChannels(String, String),
+}
+
+/// Name of an environment in an app whose repos override its channels with different ones, if any
+fn conflicting_channels(app: &App) -> Option<String> {
+  let envs = app.repos.iter().flat_map(|r| r.environments.iter()).collect::<Vec<_>>();
+  let differ = |a: &Option<String>, b: &Option<String>| matches!((a, b), (Some(a), Some(b)) if a != b);
+
+  envs.iter()
+      .find(|a| {
+        envs.iter().any(|b| {
+                     a.name_eq(&b.name)
+                     && (differ(&a.notification_channel_id, &b.notification_channel_id)
+                         || differ(&a.announcement_channel_id, &b.announcement_channel_id))
+                   })
+      })
+      .map(|env| env.name.clone())
+}
+
+/// Make sure apps only use features their chat service supports,
+/// that their deploy windows make sense,
+/// and that their repos agree on which channels to use for each environment
+fn validate(apps: Vec<App>) -> Result<Vec<App>, ReadError> {
+  let bad_window = apps.iter().find_map(|app| {
+                                app.repos
+                                   .iter()
+                                   .flat_map(|r| r.environments.iter())
+                                   .flat_map(|env| env.windows.iter())
+                                   .find_map(Window::invalid)
+                                   .map(|why| ReadError::InvalidWindow(app.name.clone(), why))
+                              });
+
+  if let Some(e) = bad_window {
+    return Err(e);
+  }
+
+  let conflict = apps.iter()
+                     .find_map(|app| conflicting_channels(app).map(|env| (app.name.clone(), env)));
+
+  if let Some((app, env)) = conflict {
+    return Err(ReadError::ConflictingChannels(app, env));
+  }
+
+  let groups = |app: &App| {
+    app.repos
+       .iter()
+       .flat_map(|r| r.environments.iter())
+       .flat_map(|env| env.users.iter())
+       .any(|user| matches!(user, User::Group { .. }))
+  };
+
+  match apps.iter().find(|app| app.chat == Chat::Mattermost && groups(app)) {
+    | Some(app) => Err(ReadError::MattermostGroups(app.name.clone())),
+    | None => Ok(apps),
+  }
 }
 
 /// A Reader is capable of producing an array of deployables,
@@ -133,28 +382,27 @@ pub trait Reader: 'static + Sync + Send + std::fmt::Debug {
   /// Read the deployables from some source
   fn read(&self) -> Result<Vec<App>, ReadError>;
 
+  /// Find an app by name in a slack workspace
+  fn get_app(&self, team_id: &str, app_name: &str) -> Result<App, super::Error> {
+    use super::Error::*;
+
+    self.read().map_err(ReadingApps).and_then(|apps| {
+                                      apps.into_iter()
+                                          .find(|app| app.team_id == team_id && app.name.loose_eq(app_name))
+                                          .ok_or_else(|| AppNotFound(app_name.to_string()))
+                                    })
+  }
+
   /// Find app matching a deploy command
   fn get_matching_cmd(&self, cmd: &super::Command) -> Result<App, super::Error> {
-    use super::{Error, Error::*};
-    use crate::{extra::StrExtra, result_extra::ResultExtra};
-
-    let loose_eq = |a: &str, b: &str| a.trim().to_lowercase() == b.trim().to_lowercase();
+    use super::Error::*;
+    use crate::result_extra::ResultExtra;
 
     let env_matches = |env: &Mergeable| -> bool { env.name.loose_eq(&cmd.env_name) };
 
     let matches_env_and_user = |app: &App| -> bool { app.repos.iter().any(|r| r.environments.iter().any(env_matches)) };
 
-    let matches_app = |app: &App| -> bool { app.team_id == cmd.team_id && loose_eq(&app.name, &cmd.app_name) };
-
-    let matches_team = |apps: Vec<App>| -> Result<App, Error> {
-      apps.into_iter()
-          .find(matches_app)
-          .ok_or_else(|| AppNotFound(cmd.app_name.clone()))
-    };
-
-    self.read()
-        .map_err(ReadingApps)
-        .and_then(matches_team)
+    self.get_app(&cmd.team_id, &cmd.app_name)
         .filter(matches_env_and_user, |_| {
           EnvNotFound(cmd.app_name.clone(), cmd.env_name.clone())
         })
@@ -170,5 +418,143 @@ impl Reader for JsonFile {
     std::fs::read_to_string(std::path::Path::new("./deployables.json"))
             .map_err(ReadError::Io)
             .and_then(|json| serde_json::from_str(&json).map_err(ReadError::Json))
+            .and_then(validate)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn app(promotion: &[&str]) -> App {
+    App { name: "mergebot".into(),
+          team_id: "T0001".into(),
+          chat: Chat::Slack,
+          notification_channel_id: "C0001".into(),
+          repos: vec![],
+          promotion: promotion.iter().map(|s| s.to_string()).collect(),
+          dm_groups: false,
+          dm_reminder_mins: None,
+          webhooks: vec![],
+          sinks: vec![] }
+  }
+
+  fn repo(environments: Vec<Mergeable>) -> Repo {
+    Repo { url: "git@foo.com:my/repo".into(),
+           human_url: "foo.com/my/repo".into(),
+           name: "repo".into(),
+           environments }
+  }
+
+  #[test]
+  fn promotion_stages() {
+    let app = app(&["qa", "staging", "prod"]);
+
+    assert_eq!(app.prev_stage("qa"), None);
+    assert_eq!(app.prev_stage("Staging"), Some("qa"));
+    assert_eq!(app.prev_stage("dev"), None);
+
+    assert_eq!(app.next_stage(None), Some("qa"));
+    assert_eq!(app.next_stage(Some("staging")), Some("prod"));
+    assert_eq!(app.next_stage(Some("prod")), None);
+  }
+
+  #[test]
+  fn channels_per_env() {
+    let env = |name: &str, channel: Option<&str>, announce: Option<&str>| {
+      serde_json::from_value::<Mergeable>(serde_json::json!({
+                                            "name": name,
+                                            "base": "main",
+                                            "target": name,
+                                            "users": [],
+                                            "notification_channel_id": channel,
+                                            "announcement_channel_id": announce,
+                                          })).unwrap()
+    };
+
+    let mut app = app(&[]);
+    app.repos = vec![repo(vec![env("staging", None, None), env("prod", Some("C0002"), Some("C0003"))])];
+
+    assert_eq!(app.notification_channel("staging"), "C0001");
+    assert_eq!(app.notification_channel("Prod"), "C0002");
+    assert_eq!(app.announcement_channel("staging"), None);
+    assert_eq!(app.announcement_channel("prod"), Some("C0003"));
+  }
+
+  #[test]
+  fn conflicting_channels_rejected() {
+    let env = |name: &str, channel: Option<&str>| {
+      serde_json::from_value::<Mergeable>(serde_json::json!({
+                                            "name": name,
+                                            "base": "main",
+                                            "target": name,
+                                            "users": [],
+                                            "notification_channel_id": channel,
+                                          })).unwrap()
+    };
+
+    let mut app = app(&[]);
+    app.repos = vec![repo(vec![env("prod", Some("C0002"))]), repo(vec![env("Prod", None)])];
+
+    assert!(validate(vec![app.clone()]).is_ok());
+
+    app.repos[1].environments = vec![env("Prod", Some("C0003"))];
+
+    assert!(matches!(validate(vec![app]),
+                     Err(ReadError::ConflictingChannels(name, env)) if name == "mergebot" && env == "prod"));
+  }
+
+  #[test]
+  fn no_promotion_chain() {
+    let app = app(&[]);
+
+    assert_eq!(app.prev_stage("prod"), None);
+    assert_eq!(app.next_stage(None), None);
+  }
+
+  #[test]
+  fn mattermost_groups_rejected() {
+    let env = serde_json::from_value::<Mergeable>(serde_json::json!({
+                                                    "name": "prod",
+                                                    "base": "main",
+                                                    "target": "prod",
+                                                    "users": [{"group_id": "S0001", "min_approvers": 1}],
+                                                  })).unwrap();
+
+    let mut app = app(&[]);
+    app.repos = vec![repo(vec![env])];
+
+    assert!(validate(vec![app.clone()]).is_ok());
+
+    app.chat = Chat::Mattermost;
+
+    assert!(matches!(validate(vec![app]), Err(ReadError::MattermostGroups(name)) if name == "mergebot"));
+  }
+
+  #[test]
+  fn invalid_windows_rejected() {
+    let env = |start_hour: u32, end_hour: u32| {
+      serde_json::from_value::<Mergeable>(serde_json::json!({
+                                            "name": "prod",
+                                            "base": "main",
+                                            "target": "prod",
+                                            "users": [],
+                                            "windows": [{
+                                              "days": ["Mon"],
+                                              "start_hour": start_hour,
+                                              "end_hour": end_hour,
+                                              "timezone": "UTC",
+                                            }],
+                                          })).unwrap()
+    };
+
+    let mut app = app(&[]);
+    app.repos = vec![repo(vec![env(22, 6)])];
+
+    assert!(validate(vec![app.clone()]).is_ok());
+
+    app.repos[0].environments = vec![env(9, 27)];
+
+    assert!(matches!(validate(vec![app]), Err(ReadError::InvalidWindow(name, _)) if name == "mergebot"));
   }
 }
@@ -0,0 +1,44 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize as De, Serialize as Ser};
+
+use crate::extra::StrExtra;
+
+/// An environment that nobody can deploy to until it's unlocked
+#[derive(PartialEq, Clone, Debug, Ser, De)]
+pub struct Lock {
+  /// Slack workspace the app belongs to
+  pub team_id: String,
+  /// App whose environment is locked
+  pub app_name: String,
+  /// Locked environment
+  pub env_name: String,
+  /// ID of user who locked the environment
+  pub user_id: String,
+  /// Why the environment is locked
+  pub reason: Option<String>,
+  /// When the environment was locked
+  pub at: DateTime<Utc>,
+}
+
+impl Lock {
+  /// Check if this lock is for an app's environment
+  pub fn matches(&self, team_id: &str, app_name: &str, env_name: &str) -> bool {
+    self.team_id == team_id && self.app_name.loose_eq(app_name) && self.env_name.loose_eq(env_name)
+  }
+}
+
+impl std::fmt::Display for Lock {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f,
+           "{} {} was locked by <@{}> at {}",
+           self.app_name,
+           self.env_name,
+           self.user_id,
+           self.at.format("%Y-%m-%d %H:%M UTC"))?;
+
+    match &self.reason {
+      | Some(reason) => write!(f, " ({})", reason),
+      | None => Ok(()),
+    }
+  }
+}
@@ -1,13 +1,26 @@
 use std::convert::TryFrom;
 
 pub use app::*;
+use chrono::{DateTime, NaiveDateTime, Utc};
+pub use lock::*;
+pub use prefs::*;
 use serde::{Deserialize as De, Serialize as Ser};
+pub use window::*;
 
 use crate::{job, slack};
 
 /// Models for local configuration file `./deployables.json`
 pub mod app;
 
+/// Deploy windows and freeze periods
+pub mod window;
+
+/// Environment locks
+pub mod lock;
+
+/// Per-user notification preferences
+pub mod prefs;
+
 /// Struct representing a parsed, well-formed /deploy command
 #[derive(Ser, De, Clone, Debug)]
 pub struct Command {
@@ -19,42 +32,437 @@ pub struct Command {
   pub user_id: String,
   /// ID of slack workspace in which deploy was triggered
   pub team_id: String,
+  /// Domain of the slack workspace, for linking to messages
+  #[serde(default)]
+  pub team_domain: String,
+  /// Deploy even if outside the environment's deploy windows or during a freeze.
+  /// Only approvers may do this.
+  #[serde(default)]
+  pub override_freeze: bool,
+  /// Once approved, wait until this time to deploy
+  #[serde(default)]
+  pub scheduled_for: Option<DateTime<Utc>>,
+}
+
+/// What a /deploy command asks for
+#[derive(Clone, Debug)]
+pub enum Action {
+  /// Deploy an app to a named environment
+  Deploy(Command),
+  /// Deploy an app to the stage of its promotion chain after the one deployed most recently.
+  /// The command's `env_name` is empty until the stage is picked with `App::next_stage`.
+  Promote(Command),
+  /// Lock an app's environment so nobody can deploy to it, optionally with a reason
+  Lock(Command, Option<String>),
+  /// Unlock an app's environment
+  Unlock(Command),
+  /// Show locks and in-progress jobs in a slack workspace
+  Status(Query),
+  /// Show recent jobs in a slack workspace
+  List(Query),
+  /// Show the apps and environments the user can deploy
+  Apps(Query),
+  /// Change whether the user is DMed when a deploy needs their approval
+  Notify(NotifyPrefs),
+  /// Show how to use `/deploy`
+  Help,
+}
+
+/// Usage of each subcommand, shown by `/deploy help` and when a command is malformed
+pub mod usage {
+  /// `/deploy <app> <env>`
+  pub const DEPLOY: &str = "`/deploy <app> <env> [at <time> | in <duration>] [--override-freeze]`";
+  /// `/deploy <app> promote`
+  pub const PROMOTE: &str = "`/deploy <app> promote [at <time> | in <duration>] [--override-freeze]`";
+  /// `/deploy lock`
+  pub const LOCK: &str = "`/deploy lock <app> <env> [reason]`";
+  /// `/deploy unlock`
+  pub const UNLOCK: &str = "`/deploy unlock <app> <env>`";
+  /// `/deploy status`
+  pub const STATUS: &str = "`/deploy status [app] [env]`";
+  /// `/deploy list`
+  pub const LIST: &str = "`/deploy list [app] [env]`";
+  /// `/deploy apps`
+  pub const APPS: &str = "`/deploy apps`";
+  /// `/deploy notify`
+  pub const NOTIFY: &str = "`/deploy notify <dm | channel>`";
+  /// `/deploy help`
+  pub const HELP: &str = "`/deploy help`";
+}
+
+/// Reply to `/deploy help`
+pub fn help() -> String {
+  let subcommands =
+    [(usage::DEPLOY,
+      "Request a deploy. `<time>` looks like `2026-10-20T09:00Z`, `<duration>` like `2h` or `1d12h`. \
+                       Approvers can deploy during a freeze or outside deploy windows with `--override-freeze`."),
+     (usage::PROMOTE, "Deploy the stage of the app's promotion chain after the one deployed most recently."),
+     (usage::LOCK, "Stop anyone from deploying to an environment, e.g. during an incident."),
+     (usage::UNLOCK, "Allow deploys to a locked environment again."),
+     (usage::STATUS, "Show locked environments and deploys in progress."),
+     (usage::LIST, "Show recent deploys."),
+     (usage::APPS, "Show the apps and environments you can deploy."),
+     (usage::NOTIFY,
+      "Choose whether I DM you when a deploy needs your approval (the default), \
+       or only ask in the app's channel."),
+     (usage::HELP, "Show this message.")];
+
+  subcommands.iter()
+             .map(|(usage, about)| format!("{}\n>{}", usage, about))
+             .collect::<Vec<_>>()
+             .join("\n")
+}
+
+/// Narrows down what `/deploy status` and `/deploy list` show
+#[derive(Ser, De, Clone, Debug)]
+pub struct Query {
+  /// ID of slack workspace in which the command was sent
+  pub team_id: String,
+  /// Domain of the slack workspace, for linking to messages
+  pub team_domain: String,
+  /// ID of user who sent the command
+  pub user_id: String,
+  /// Only show this app
+  pub app_name: Option<String>,
+  /// Only show this environment
+  pub env_name: Option<String>,
+}
+
+impl Query {
+  /// Check if an app's environment is one this query is interested in
+  pub fn matches(&self, team_id: &str, app_name: &str, env_name: &str) -> bool {
+    use crate::extra::StrExtra;
+
+    let matches = |name: &str, filter: &Option<String>| filter.as_ref().map(|f| name.loose_eq(f)).unwrap_or(true);
+
+    self.team_id == team_id && matches(app_name, &self.app_name) && matches(env_name, &self.env_name)
+  }
 }
 
 /// Any error around the /deploy command
 #[derive(Debug)]
 pub enum Error {
   /// There's a pending deploy already
-  JobAlreadyQueued(job::Job<job::States>),
+  JobAlreadyQueued(Box<job::Job<job::States>>),
   /// Slash command sent was not deploy
   CommandNotDeploy,
   /// Error encountered trying to read `deployables.json`
   ReadingApps(app::ReadError),
-  /// Slash command was malformed (multiple arguments, not enough)
-  CommandMalformed,
+  /// Slash command was malformed (multiple arguments, not enough).
+  /// Includes the usage of the subcommand that was attempted.
+  CommandMalformed(&'static str),
   /// Application not found in Apps
   AppNotFound(String),
   /// Environment not found in application
   EnvNotFound(String, String),
   /// Error interacting with slack
   SlackApi(slack::Error),
+  /// Environment can't be deployed right now
+  Blocked(Blocked),
+  /// User asked to override a freeze but isn't an approver
+  OverrideForbidden,
+  /// Time given by `at` or `in` couldn't be parsed or is in the past
+  InvalidTime(String),
+  /// App (named) doesn't have a promotion chain
+  NoPromotionChain(String),
+  /// App (named) was most recently deployed to the last stage of its promotion chain
+  NothingToPromote(String),
+  /// Environment is locked
+  Locked(Box<Lock>),
+  /// App (named) environment (named) isn't locked
+  NotLocked(String, String),
+  /// User isn't one of the app's (named) environment's (named) users
+  NotAllowed(String, String),
+}
+
+impl std::fmt::Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      | Self::JobAlreadyQueued(job) => write!(f,
+                                              "There's already a {env} deploy in progress for {app}. \
+                                               See `/deploy status {app}`.",
+                                              env = job.command.env_name,
+                                              app = job.app.name),
+      | Self::CommandNotDeploy => write!(f, "I only know how to handle `/deploy`."),
+      | Self::CommandMalformed(usage) => {
+        write!(f,
+               "I didn't understand that. Usage: {}\nSee `/deploy help` for more.",
+               usage)
+      },
+      | Self::AppNotFound(app) => write!(f, "I couldn't find an app named {}. See `/deploy apps`.", app),
+      | Self::EnvNotFound(app, env) => {
+        write!(f,
+               "{} doesn't have an environment named {}. See `/deploy apps`.",
+               app, env)
+      },
+      | Self::NotAllowed(app, env) => write!(f, "You aren't one of the users who can deploy {} {}.", app, env),
+      | Self::Blocked(blocked) => write!(f,
+                                         "{}. An approver can deploy anyway with `--override-freeze`.",
+                                         blocked),
+      | Self::OverrideForbidden => write!(f, "Only approvers can use `--override-freeze`."),
+      | Self::InvalidTime(time) => write!(f,
+                                          "I couldn't schedule a deploy for \"{}\". \
+                                           Try something like `at 2026-10-20T09:00Z` or `in 2h`.",
+                                          time),
+      | Self::NoPromotionChain(app) => write!(f, "{} doesn't have a promotion chain.", app),
+      | Self::NothingToPromote(app) => write!(f,
+                                              "{} was last deployed to the final stage of its promotion chain, \
+                                               so there's nothing to promote.",
+                                              app),
+      | Self::Locked(lock) => write!(f,
+                                     ":lock: {}. Run `/deploy unlock {} {}` once it's safe to deploy.",
+                                     lock, lock.app_name, lock.env_name),
+      | Self::NotLocked(app, env) => write!(f, "{} {} isn't locked.", app, env),
+      | Self::ReadingApps(_) | Self::SlackApi(_) => {
+        let uh_oh = "Uh oh :confused: I wasn't able to do that.";
+        let link = "https://github.com/cakekindel/mergebot/issues";
+
+        write!(f,
+               "{} <{}|Please file an issue> and let Orion know there's a bug!",
+               uh_oh, link)
+      },
+    }
+  }
+}
+
+/// Parse a time like `2026-10-20T09:00Z` or `2026-10-20T09:00:00-04:00`
+fn parse_at(time: &str) -> Option<DateTime<Utc>> {
+  let utc = |dt: DateTime<chrono::FixedOffset>| dt.with_timezone(&Utc);
+
+  DateTime::parse_from_rfc3339(time).map(utc)
+                                    .or_else(|_| DateTime::parse_from_str(time, "%Y-%m-%dT%H:%M%:z").map(utc))
+                                    .ok()
+                                    .or_else(|| {
+                                      time.strip_suffix('Z')
+                                          .and_then(|time| NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M").ok())
+                                          .map(|time| DateTime::from_utc(time, Utc))
+                                    })
+}
+
+/// Parse a duration like `2h`, `90m` or `1d12h`
+fn parse_in(dur: &str) -> Option<chrono::Duration> {
+  use chrono::Duration as Dur;
+
+  let mut total = Dur::zero();
+  let mut digits = String::new();
+
+  for c in dur.chars() {
+    if c.is_ascii_digit() {
+      digits.push(c);
+      continue;
+    }
+
+    let n = digits.parse::<u32>().ok().map(i64::from)?;
+    digits.clear();
+
+    let part = match c {
+      | 'd' => Dur::days(n),
+      | 'h' => Dur::hours(n),
+      | 'm' => Dur::minutes(n),
+      | 's' => Dur::seconds(n),
+      | _ => return None,
+    };
+
+    total = total.checked_add(&part)?;
+  }
+
+  Some(total).filter(|total| digits.is_empty() && *total > Dur::zero())
 }
 
-impl TryFrom<slack::SlashCommand> for Command {
+/// Parse the optional `at <time>` or `in <duration>` suffix of a deploy command
+fn parse_schedule(words: &[String], usage: &'static str) -> Result<Option<DateTime<Utc>>, Error> {
+  let invalid = || Error::InvalidTime(words.join(" "));
+
+  let time = match words {
+    | [] => return Ok(None),
+    | [at, time] if at == "at" => parse_at(time),
+    | [in_, dur] if in_ == "in" => parse_in(dur).and_then(|dur| Utc::now().checked_add_signed(dur)),
+    | _ => return Err(Error::CommandMalformed(usage)),
+  };
+
+  time.filter(|time| time > &Utc::now()).map(Some).ok_or_else(invalid)
+}
+
+/// Usage of a subcommand other than deploying, if `word` is one
+fn subcommand_usage(word: &str) -> Option<&'static str> {
+  match word {
+    | "help" => Some(usage::HELP),
+    | "apps" => Some(usage::APPS),
+    | "lock" => Some(usage::LOCK),
+    | "unlock" => Some(usage::UNLOCK),
+    | "status" => Some(usage::STATUS),
+    | "list" => Some(usage::LIST),
+    | "notify" => Some(usage::NOTIFY),
+    | _ => None,
+  }
+}
+
+impl TryFrom<slack::SlashCommand> for Action {
   type Error = Error;
 
   fn try_from(cmd: slack::SlashCommand) -> Result<Self, Self::Error> {
-    Ok(cmd).and_then(|cmd| match cmd.command.as_str() {
-             | "/deploy" => Ok(cmd),
-             | _ => Err(Error::CommandNotDeploy),
-           })
-           .and_then(|cmd| match cmd.text.clone().split(' ').collect::<Vec<_>>().as_slice() {
-             | [app, env] => Ok((cmd, app.to_string(), env.to_string())),
-             | _ => Err(Error::CommandMalformed),
-           })
-           .map(|(cmd, app_name, env_name)| Command { team_id: cmd.team_id,
-                                                      user_id: cmd.user_id,
-                                                      app_name,
-                                                      env_name })
+    if cmd.command != "/deploy" {
+      return Err(Error::CommandNotDeploy);
+    }
+
+    let (flags, args): (Vec<String>, Vec<String>) = cmd.text
+                                                       .split_whitespace()
+                                                       .map(String::from)
+                                                       .partition(|word| word.starts_with("--"));
+
+    // `/deploy` on its own shows help
+    let (sub, rest) = args.split_first()
+                          .map(|(sub, rest)| (sub.as_str(), rest))
+                          .unwrap_or(("help", &[][..]));
+
+    let override_freeze = flags.iter().any(|flag| flag == "--override-freeze");
+
+    let command =
+      |app: &str, env: &str, scheduled_for: Option<DateTime<Utc>>| Command { team_id: cmd.team_id.clone(),
+                                                                             team_domain: cmd.team_domain.clone(),
+                                                                             user_id: cmd.user_id.clone(),
+                                                                             app_name: app.to_string(),
+                                                                             env_name: env.to_string(),
+                                                                             override_freeze,
+                                                                             scheduled_for };
+
+    let query = |app: Option<&String>, env: Option<&String>| Query { team_id: cmd.team_id.clone(),
+                                                                     team_domain: cmd.team_domain.clone(),
+                                                                     user_id: cmd.user_id.clone(),
+                                                                     app_name: app.cloned(),
+                                                                     env_name: env.cloned() };
+
+    if let Some(usage) = subcommand_usage(sub) {
+      let action = match (sub, rest) {
+        | ("help", []) => Some(Action::Help),
+        | ("apps", []) => Some(Action::Apps(query(None, None))),
+        | ("lock", [app, env, reason @ ..]) => {
+          let reason = Some(reason.join(" ")).filter(|reason| !reason.is_empty());
+          Some(Action::Lock(command(app, env, None), reason))
+        },
+        | ("unlock", [app, env]) => Some(Action::Unlock(command(app, env, None))),
+        | ("status", filters) if filters.len() <= 2 => Some(Action::Status(query(filters.first(), filters.get(1)))),
+        | ("list", filters) if filters.len() <= 2 => Some(Action::List(query(filters.first(), filters.get(1)))),
+        | ("notify", [how]) if how == "dm" || how == "channel" => {
+          Some(Action::Notify(NotifyPrefs { team_id: cmd.team_id.clone(),
+                                            user_id: cmd.user_id.clone(),
+                                            dm: how == "dm" }))
+        },
+        | _ => None,
+      };
+
+      // subcommands other than deploying don't take flags
+      return action.filter(|_| flags.is_empty())
+                   .ok_or(Error::CommandMalformed(usage));
+    }
+
+    let usage = match rest.first() {
+      | Some(word) if word == "promote" => usage::PROMOTE,
+      | _ => usage::DEPLOY,
+    };
+
+    match rest {
+      | _ if flags.iter().any(|flag| flag != "--override-freeze") => Err(Error::CommandMalformed(usage)),
+      | [promote, schedule @ ..] if promote == "promote" => {
+        parse_schedule(schedule, usage).map(|at| Action::Promote(command(sub, "", at)))
+      },
+      | [env, schedule @ ..] => parse_schedule(schedule, usage).map(|at| Action::Deploy(command(sub, env, at))),
+      | [] => Err(Error::CommandMalformed(usage)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+
+  use super::*;
+
+  #[test]
+  fn parse_at_formats() {
+    let expected = Utc.ymd(2026, 10, 20).and_hms(9, 0, 0);
+
+    assert_eq!(parse_at("2026-10-20T09:00Z"), Some(expected));
+    assert_eq!(parse_at("2026-10-20T09:00:00Z"), Some(expected));
+    assert_eq!(parse_at("2026-10-20T05:00-04:00"), Some(expected));
+    assert_eq!(parse_at("tomorrow"), None);
+  }
+
+  fn slash(text: &str) -> slack::SlashCommand {
+    slack::SlashCommand { command: "/deploy".into(),
+                          text: text.into(),
+                          channel_id: "C0001".into(),
+                          team_id: "T0001".into(),
+                          response_url: "https://hooks.slack.com/commands/1234/5678".into(),
+                          team_domain: "example".into(),
+                          user_id: "U0001".into() }
+  }
+
+  #[test]
+  fn parse_action() {
+    match Action::try_from(slash("mergebot prod --override-freeze")) {
+      | Ok(Action::Deploy(cmd)) => {
+        assert_eq!(cmd.app_name, "mergebot");
+        assert_eq!(cmd.env_name, "prod");
+        assert!(cmd.override_freeze);
+      },
+      | other => panic!("{:?}", other),
+    }
+
+    match Action::try_from(slash("mergebot promote in 1h")) {
+      | Ok(Action::Promote(cmd)) => assert!(cmd.scheduled_for.is_some()),
+      | other => panic!("{:?}", other),
+    }
+
+    match Action::try_from(slash("lock mergebot prod db migration went sideways")) {
+      | Ok(Action::Lock(cmd, reason)) => {
+        assert_eq!(cmd.env_name, "prod");
+        assert_eq!(reason.as_deref(), Some("db migration went sideways"));
+      },
+      | other => panic!("{:?}", other),
+    }
+
+    match Action::try_from(slash("status mergebot")) {
+      | Ok(Action::Status(query)) => {
+        assert_eq!(query.app_name.as_deref(), Some("mergebot"));
+        assert_eq!(query.env_name, None);
+      },
+      | other => panic!("{:?}", other),
+    }
+
+    assert!(matches!(Action::try_from(slash("")), Ok(Action::Help)));
+    assert!(matches!(Action::try_from(slash("apps")), Ok(Action::Apps(_))));
+    assert!(matches!(Action::try_from(slash("notify channel")),
+                     Ok(Action::Notify(NotifyPrefs { dm: false, .. }))));
+    assert!(matches!(Action::try_from(slash("notify sms")),
+                     Err(Error::CommandMalformed(usage::NOTIFY))));
+
+    assert!(matches!(Action::try_from(slash("mergebot")),
+                     Err(Error::CommandMalformed(usage::DEPLOY))));
+    assert!(matches!(Action::try_from(slash("mergebot promote at noon please")),
+                     Err(Error::CommandMalformed(usage::PROMOTE))));
+    assert!(matches!(Action::try_from(slash("unlock mergebot")),
+                     Err(Error::CommandMalformed(usage::UNLOCK))));
+    assert!(matches!(Action::try_from(slash("status --override-freeze")),
+                     Err(Error::CommandMalformed(usage::STATUS))));
+    assert!(matches!(Action::try_from(slash("mergebot prod --force")),
+                     Err(Error::CommandMalformed(usage::DEPLOY))));
+  }
+
+  #[test]
+  fn parse_in_formats() {
+    assert_eq!(parse_in("2h"), Some(chrono::Duration::hours(2)));
+    assert_eq!(parse_in("1d12h"), Some(chrono::Duration::hours(36)));
+    assert_eq!(parse_in("90m"), Some(chrono::Duration::minutes(90)));
+    assert_eq!(parse_in("2"), None);
+    assert_eq!(parse_in("2w"), None);
+    assert_eq!(parse_in("0m"), None);
+    assert_eq!(parse_in(&"4000000000d".repeat(30)), None);
+  }
+
+  #[test]
+  fn schedule_too_far_out() {
+    assert!(matches!(Action::try_from(slash("mergebot prod in 4000000000d")),
+                     Err(Error::InvalidTime(_))));
   }
 }
@@ -0,0 +1,19 @@
+use serde::{Deserialize as De, Serialize as Ser};
+
+/// How a user wants to be told that a deploy needs their approval
+#[derive(PartialEq, Clone, Debug, Ser, De)]
+pub struct NotifyPrefs {
+  /// Slack workspace the user belongs to
+  pub team_id: String,
+  /// ID of the user
+  pub user_id: String,
+  /// Whether to DM the user, on top of asking in the app's notification channel
+  pub dm: bool,
+}
+
+impl NotifyPrefs {
+  /// Check if these are a user's preferences
+  pub fn matches(&self, team_id: &str, user_id: &str) -> bool {
+    self.team_id == team_id && self.user_id == user_id
+  }
+}
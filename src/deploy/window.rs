@@ -0,0 +1,251 @@
+use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike, Utc, Weekday};
+use serde::{Deserialize as De, Serialize as Ser};
+
+use super::Lock;
+
+fn utc() -> String {
+  String::from("UTC")
+}
+
+/// How finely to look ahead for a window opening. Every timezone's offset is a multiple of this.
+const OPENING_STEP_MINS: i64 = 15;
+
+/// A recurring window of time during which deploys are allowed
+#[derive(PartialEq, Clone, Debug, Ser, De)]
+pub struct Window {
+  /// Days of the week the window is open on (e.g. `["Mon", "Tue"]`)
+  pub days: Vec<Weekday>,
+  /// Hour of the day (`0` - `23`) the window opens
+  pub start_hour: u32,
+  /// Hour of the day (`1` - `24`) the window closes. Exclusive.
+  ///
+  /// If this is before `start_hour`, the window closes the next morning (e.g. `22` - `6`),
+  /// and `days` are the days it opens on.
+  pub end_hour: u32,
+  /// IANA name of the timezone `days` and hours are in (e.g. `America/New_York`). Defaults to `UTC`.
+  #[serde(default = "utc")]
+  pub timezone: String,
+}
+
+impl Window {
+  /// Why the window can't be used, if it can't
+  pub fn invalid(&self) -> Option<String> {
+    if self.days.is_empty() {
+      Some(format!("{} is never open, since it has no days", self))
+    } else if self.start_hour > 23 || self.end_hour > 24 || self.start_hour == self.end_hour {
+      Some(format!("{} has hours outside 0-24, or opens and closes at the same hour", self))
+    } else if let Err(e) = self.timezone.parse::<chrono_tz::Tz>() {
+      Some(format!("{} has an invalid timezone: {}", self, e))
+    } else {
+      None
+    }
+  }
+
+  /// Is the window open at a point in time?
+  ///
+  /// Windows with a timezone that can't be parsed are never open.
+  pub fn contains(&self, at: DateTime<Utc>) -> bool {
+    match self.timezone.parse::<chrono_tz::Tz>() {
+      | Ok(tz) => {
+        let local = at.with_timezone(&tz);
+        let opened_on = |day: Weekday| self.days.contains(&day);
+
+        match self.start_hour < self.end_hour {
+          | true => opened_on(local.weekday()) && (self.start_hour..self.end_hour).contains(&local.hour()),
+          // overnight, so we're either in the evening it opened or the morning after
+          | false => {
+            (opened_on(local.weekday()) && local.hour() >= self.start_hour)
+            || (opened_on(local.weekday().pred()) && local.hour() < self.end_hour)
+          },
+        }
+      },
+      | Err(e) => {
+        log::error!("deploy window has invalid timezone {:?}: {}", self.timezone, e);
+        false
+      },
+    }
+  }
+
+  /// When the window next opens after a point in time, if it ever does
+  pub fn next_open(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let step = Duration::minutes(OPENING_STEP_MINS);
+    let first = Utc.timestamp(after.timestamp() - after.timestamp() % step.num_seconds(), 0) + step;
+
+    // a window opens at least once a week, or never does
+    (0..(8 * 24 * 60 / OPENING_STEP_MINS)).map(|n| first + step * n as i32)
+                                          .find(|at| self.contains(*at))
+  }
+}
+
+impl std::fmt::Display for Window {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let days = self.days.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+
+    write!(f,
+           "{} {:02}:00-{:02}:00 {}",
+           days.join(", "),
+           self.start_hour,
+           self.end_hour,
+           self.timezone)
+  }
+}
+
+/// A period of time during which deploys are not allowed
+#[derive(PartialEq, Clone, Debug, Ser, De)]
+pub struct Freeze {
+  /// When the freeze begins
+  pub start: DateTime<Utc>,
+  /// When the freeze ends
+  pub end: DateTime<Utc>,
+  /// Why deploys are frozen
+  #[serde(default)]
+  pub reason: Option<String>,
+}
+
+impl Freeze {
+  /// Is a point in time within the freeze?
+  pub fn contains(&self, at: DateTime<Utc>) -> bool {
+    self.start <= at && at < self.end
+  }
+}
+
+/// Why a deploy isn't allowed at some point in time
+#[derive(PartialEq, Clone, Debug, Ser, De)]
+pub enum Blocked {
+  /// The environment (named) is frozen
+  Frozen(String, Freeze),
+  /// The time is outside all of the environment's (named) deploy windows
+  OutsideWindows(String, Vec<Window>),
+  /// Someone locked the environment
+  Locked(Box<Lock>),
+}
+
+impl Blocked {
+  /// When a deploy blocked for this reason could next be allowed, if we can tell
+  pub fn until(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    match self {
+      | Self::Frozen(_, freeze) => Some(freeze.end),
+      | Self::OutsideWindows(_, windows) => windows.iter().filter_map(|w| w.next_open(now)).min(),
+      | Self::Locked(_) => None,
+    }
+  }
+}
+
+impl std::fmt::Display for Blocked {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      | Self::Frozen(env, Freeze { end, reason, .. }) => {
+        write!(f,
+               "Deploys to {} are frozen until {}",
+               env,
+               end.format("%Y-%m-%d %H:%M UTC"))?;
+
+        match reason {
+          | Some(reason) => write!(f, " ({})", reason),
+          | None => Ok(()),
+        }
+      },
+      | Self::OutsideWindows(env, windows) => {
+        let windows = windows.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        write!(f, "{} can only be deployed {}", env, windows.join(" or "))
+      },
+      | Self::Locked(lock) => write!(f, ":lock: {}", lock),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+
+  use super::*;
+
+  fn business_hours() -> Window {
+    Window { days: vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri],
+             start_hour: 9,
+             end_hour: 17,
+             timezone: "America/New_York".into() }
+  }
+
+  #[test]
+  fn window_contains() {
+    let window = business_hours();
+
+    // Friday 2026-10-16 16:30 in New York
+    assert!(window.contains(Utc.ymd(2026, 10, 16).and_hms(20, 30, 0)));
+
+    // Friday 2026-10-16 17:30 in New York
+    assert!(!window.contains(Utc.ymd(2026, 10, 16).and_hms(21, 30, 0)));
+
+    // Saturday 2026-10-17 10:00 in New York
+    assert!(!window.contains(Utc.ymd(2026, 10, 17).and_hms(14, 0, 0)));
+  }
+
+  #[test]
+  fn window_overnight() {
+    let window = Window { days: vec![Weekday::Fri],
+                          start_hour: 22,
+                          end_hour: 6,
+                          timezone: "UTC".into() };
+
+    assert!(window.invalid().is_none());
+    assert!(window.contains(Utc.ymd(2026, 10, 16).and_hms(23, 0, 0)));
+    assert!(window.contains(Utc.ymd(2026, 10, 17).and_hms(5, 59, 0)));
+    assert!(!window.contains(Utc.ymd(2026, 10, 17).and_hms(6, 0, 0)));
+    assert!(!window.contains(Utc.ymd(2026, 10, 17).and_hms(23, 0, 0)));
+    assert!(!window.contains(Utc.ymd(2026, 10, 16).and_hms(5, 0, 0)));
+  }
+
+  #[test]
+  fn window_invalid_hours() {
+    let window = |start_hour, end_hour| Window { start_hour,
+                                                 end_hour,
+                                                 ..business_hours() };
+
+    assert!(window(9, 9).invalid().is_some());
+    assert!(window(24, 6).invalid().is_some());
+    assert!(window(9, 25).invalid().is_some());
+    assert!(window(0, 24).invalid().is_none());
+    assert!(business_hours().invalid().is_none());
+  }
+
+  #[test]
+  fn window_next_open() {
+    let window = business_hours();
+
+    // Friday 2026-10-16 17:30 in New York, so next Monday 09:00
+    assert_eq!(window.next_open(Utc.ymd(2026, 10, 16).and_hms(21, 30, 0)),
+               Some(Utc.ymd(2026, 10, 19).and_hms(13, 0, 0)));
+
+    let never = Window { days: vec![],
+                         ..business_hours() };
+    assert_eq!(never.next_open(Utc.ymd(2026, 10, 16).and_hms(21, 30, 0)), None);
+  }
+
+  #[test]
+  fn window_bad_timezone() {
+    let window = Window { timezone: "Not/AZone".into(),
+                          ..business_hours() };
+
+    assert!(!window.contains(Utc.ymd(2026, 10, 16).and_hms(20, 30, 0)));
+  }
+
+  #[test]
+  fn window_de() {
+    let json = r#"{"days": ["Mon", "friday"], "start_hour": 9, "end_hour": 17}"#;
+    let window = serde_json::from_str::<Window>(json).unwrap();
+
+    assert_eq!(window.days, vec![Weekday::Mon, Weekday::Fri]);
+    assert_eq!(window.timezone, "UTC");
+  }
+
+  #[test]
+  fn freeze_contains() {
+    let freeze = Freeze { start: Utc.ymd(2026, 12, 20).and_hms(0, 0, 0),
+                          end: Utc.ymd(2027, 1, 4).and_hms(0, 0, 0),
+                          reason: Some("holidays".into()) };
+
+    assert!(freeze.contains(Utc.ymd(2026, 12, 25).and_hms(12, 0, 0)));
+    assert!(!freeze.contains(Utc.ymd(2027, 1, 4).and_hms(0, 0, 0)));
+  }
+}
@@ -0,0 +1,28 @@
+use std::{fs, io, path::Path};
+
+/// Write `contents` to the file at `path` by writing a sibling temp file and renaming it over `path`,
+/// so that a crash mid-write leaves either the old or the new contents, never half of each.
+pub fn write_atomic(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+  let path = path.as_ref();
+  let mut tmp = path.as_os_str().to_owned();
+  tmp.push(".tmp");
+
+  fs::write(&tmp, contents)?;
+  fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn write_atomic_replaces() {
+    let path = std::env::temp_dir().join(format!("mergebot-write-atomic-{}", nanoid::nanoid!()));
+
+    write_atomic(&path, "foo").unwrap();
+    write_atomic(&path, "bar").unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "bar");
+    fs::remove_file(&path).ok();
+  }
+}
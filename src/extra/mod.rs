@@ -1,2 +1,5 @@
 mod r#str;
 pub use self::r#str::*;
+
+mod fs;
+pub use self::fs::*;
@@ -1,9 +1,12 @@
 use std::sync::{Mutex, MutexGuard};
 
-use git::{r#impl::LocalClient, Branch, Error, Output};
+use git::{r#impl::LocalClient, Branch, Commit, Error, Output, Sha};
 
 use crate::{git, mutex_extra::lock_discard_poison, result_extra::ResultExtra};
 
+/// Message of the merge commits we make when deploying
+const DEPLOY_MERGE_MESSAGE: &str = "chore: mergebot deploy";
+
 pub(super) struct RepoContext<'a> {
   log_prefix: String,
   lock: MutexGuard<'a, Option<LocalClient>>,
@@ -28,6 +31,9 @@ impl<'a> RepoContext<'a> {
 }
 
 impl<'a> git::RepoContext for RepoContext<'a> {
+  fn workdir(&self) -> std::path::PathBuf {
+    self.client(|c| lock_discard_poison(&c.workdir).clone())
+  }
   fn upstream(&self, branch: &Branch) -> git::Result<Branch> {
     let config_entry = format!("branch.{}.remote", branch.0);
     self.client(|c| {
@@ -39,8 +45,15 @@ impl<'a> git::RepoContext for RepoContext<'a> {
         .tap_err(|err| log::error!("{}(upstream) {:?}", self.log_prefix, err))
   }
 
+  fn sha(&self, branch: &Branch) -> git::Result<Sha> {
+    self.client(|c| c.git(&["rev-parse", &branch.0]))
+        .map(|Output(sha)| Sha(sha.trim().to_string()))
+        .tap(|ok| log::info!("{}(sha {:?}) {:?}", self.log_prefix, branch, ok))
+        .tap_err(|err| log::error!("{}(sha {:?}) {:?}", self.log_prefix, branch, err))
+  }
+
   fn merge(&self, target: &Branch) -> git::Result<()> {
-    self.client(|c| c.git(&["merge", &target.0, "--message", "chore: mergebot deploy"]))
+    self.client(|c| c.git(&["merge", &target.0, "--message", DEPLOY_MERGE_MESSAGE]))
         .tap(|ok| {
           log::info!("{}(merge {:?} -> {:?}) {:?}",
                      self.log_prefix,
@@ -110,6 +123,65 @@ impl<'a> git::RepoContext for RepoContext<'a> {
         .tap_err(|err| log::error!("`{}(fetch_all) {:?}", self.log_prefix, err))
         .map(|_| ())
   }
+
+  fn last_pushed(&self, branch: &Branch) -> git::Result<Option<Sha>> {
+    let remote_ref = self.upstream(branch).map(|up| format!("refs/remotes/{}", up.0))?;
+
+    // pushing moves our remote-tracking branch, and its reflog outlives the process
+    self.client(|c| c.git(&["reflog", "show", "--format=%H %gs", &remote_ref]))
+        .map(|Output(out)| {
+          out.lines()
+             .find_map(|entry| entry.strip_suffix(" update by push"))
+             .map(|sha| Sha(sha.to_string()))
+        })
+        .tap(|ok| log::info!("{}(last_pushed {:?}) {:?}", self.log_prefix, branch, ok))
+        .tap_err(|err| log::error!("{}(last_pushed {:?}) {:?}", self.log_prefix, branch, err))
+  }
+
+  fn log(&self, from: &Sha, to: &Sha) -> git::Result<Vec<Commit>> {
+    let range = format!("{}..{}", from.0, to.0);
+
+    // ASCII unit & record separators won't show up in commit messages
+    self.client(|c| {
+          c.git(&["log",
+                  "--first-parent",
+                  "--format=%H%x1f%P%x1f%an%x1f%s%x1f%b%x1e",
+                  &range])
+        })
+        .map(|Output(out)| parse_log(&out))
+        .and_then(|entries| {
+          entries.into_iter().try_fold(vec![], |mut commits, (parents, commit)| {
+                               // our own deploy merges aren't interesting, but what they merged is
+                               match parents.get(1) {
+                                 | Some(merged) if commit.subject == DEPLOY_MERGE_MESSAGE => {
+                                   commits.extend(self.log(from, &Sha(merged.clone()))?)
+                                 },
+                                 | _ => commits.push(commit),
+                               }
+
+                               Ok(commits)
+                             })
+        })
+        .tap(|ok| log::info!("{}(log {}) {} commits", self.log_prefix, range, ok.len()))
+        .tap_err(|err| log::error!("{}(log {}) {:?}", self.log_prefix, range, err))
+  }
+}
+
+/// Parse the output of `git log` as formatted by `RepoContext::log` into each commit & its parents
+fn parse_log(out: &str) -> Vec<(Vec<String>, Commit)> {
+  out.split('\x1e')
+     .filter_map(|record| {
+       let mut fields = record.trim_start().split('\x1f');
+
+       let sha = fields.next().filter(|sha| !sha.is_empty())?;
+       let parents = fields.next()?.split_whitespace().map(String::from).collect();
+       let author = fields.next()?;
+       let subject = fields.next()?;
+       let body = fields.next().unwrap_or_default();
+
+       Some((parents, Commit::new(Sha(sha.to_string()), author, subject, body)))
+     })
+     .collect()
 }
 
 impl<'a> Drop for RepoContext<'a> {
@@ -12,16 +12,77 @@ impl From<&str> for Branch {
   }
 }
 
+/// A commit hash
+#[derive(PartialEq, Clone, Debug, Ser, De)]
+pub struct Sha(pub String);
+
+impl Sha {
+  /// Abbreviated hash, for displaying
+  pub fn short(&self) -> &str {
+    self.0.get(..7).unwrap_or(&self.0)
+  }
+}
+
+/// A commit, as it appears in a changelog
+#[derive(PartialEq, Clone, Debug, Ser, De)]
+pub struct Commit {
+  /// Hash of the commit
+  pub sha: Sha,
+  /// Name of the commit's author
+  pub author: String,
+  /// First line of the commit message, or the title of the pull request it merged
+  pub subject: String,
+  /// Number of the pull request the commit merged, if any
+  pub pr: Option<u32>,
+}
+
+impl Commit {
+  /// Describe a commit from its hash, author and message.
+  ///
+  /// Picks the pull request number out of github's merge commits
+  /// (`Merge pull request #12 from ...`, with the pull request's title in the body)
+  /// and squash merges (`Add a thing (#12)`).
+  pub fn new(sha: Sha, author: impl ToString, subject: &str, body: &str) -> Self {
+    let merged_pr = subject.strip_prefix("Merge pull request #")
+                           .and_then(|rest| rest.split_whitespace().next())
+                           .and_then(|num| num.parse().ok());
+
+    let squashed_pr = subject.trim_end()
+                             .strip_suffix(')')
+                             .and_then(|rest| rest.rsplit_once("(#"))
+                             .and_then(|(_, num)| num.parse().ok());
+
+    let subject = match merged_pr {
+      | Some(_) => body.lines()
+                       .map(str::trim)
+                       .find(|line| !line.is_empty())
+                       .unwrap_or(subject),
+      | None => subject,
+    };
+
+    Self { sha,
+           author: author.to_string(),
+           subject: subject.trim().to_string(),
+           pr: merged_pr.or(squashed_pr) }
+  }
+}
+
 /// Some raw command output (stdout or stderr)
 #[derive(PartialEq, Clone, Debug, Ser, De)]
 pub struct Output(String);
 
 impl Output {
-  fn from_bytes(b: impl AsRef<[u8]>) -> Self {
+  pub(crate) fn from_bytes(b: impl AsRef<[u8]>) -> Self {
     Self(String::from_utf8_lossy(b.as_ref()).to_string())
   }
 }
 
+impl AsRef<str> for Output {
+  fn as_ref(&self) -> &str {
+    &self.0
+  }
+}
+
 /// Git errors
 #[derive(Ser, De, PartialEq, Clone, Debug)]
 pub enum Error {
@@ -47,9 +108,15 @@ pub trait Client: 'static + Sync + Send + std::fmt::Debug {
 
 /// A git repo context
 pub trait RepoContext {
+  /// Directory the repo is cloned into
+  fn workdir(&self) -> std::path::PathBuf;
+
   /// Get the name of a branch's upstream
   fn upstream(&self, branch: &Branch) -> self::Result<Branch>;
 
+  /// Get the commit a branch points to
+  fn sha(&self, branch: &Branch) -> self::Result<Sha>;
+
   /// Merge a target branch into current
   fn merge(&self, target: &Branch) -> self::Result<()>;
 
@@ -64,4 +131,36 @@ pub trait RepoContext {
 
   /// Pull any untracked upstream branches
   fn fetch_all(&self) -> self::Result<()>;
+
+  /// The commit most recently pushed from this clone to a branch's upstream, if any ever was
+  fn last_pushed(&self, branch: &Branch) -> self::Result<Option<Sha>>;
+
+  /// Commits between two commits, newest first, for changelogs.
+  /// Merged branches show up as their merge commit rather than every commit on them.
+  fn log(&self, from: &Sha, to: &Sha) -> self::Result<Vec<Commit>>;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn commit_pr() {
+    let sha = || Sha("0123456789abcdef".into());
+
+    let merged = Commit::new(sha(),
+                             "Orion",
+                             "Merge pull request #42 from cakekindel/feat",
+                             "\nAdd a thing\n");
+    assert_eq!(merged.subject, "Add a thing");
+    assert_eq!(merged.pr, Some(42));
+
+    let squashed = Commit::new(sha(), "Orion", "Add a thing (#43)", "");
+    assert_eq!(squashed.subject, "Add a thing (#43)");
+    assert_eq!(squashed.pr, Some(43));
+
+    let plain = Commit::new(sha(), "Orion", "fix: typo (oops)", "");
+    assert_eq!(plain.subject, "fix: typo (oops)");
+    assert_eq!(plain.pr, None);
+  }
 }
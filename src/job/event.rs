@@ -12,6 +12,10 @@ pub enum Event<'a> {
   Approved(&'a Job<StateInit>, &'a crate::deploy::User),
   /// Job fully approved
   FullyApproved(&'a Job<StateApproved>),
+  /// Job fully approved, and will be executed later
+  Scheduled(&'a Job<StateScheduled>),
+  /// Scheduled job cancelled
+  Cancelled(&'a Job<StateCancelled>),
   /// Job errored
   Errored(&'a Job<StateErrored>),
   /// Job poisoned
@@ -19,3 +23,36 @@ pub enum Event<'a> {
   /// Job complete
   Done(&'a Job<StateDone>),
 }
+
+impl Event<'_> {
+  /// Name of the event, e.g. `created`
+  pub fn name(&self) -> &'static str {
+    match self {
+      | Self::Created(_) => "created",
+      | Self::Approved(..) => "approved",
+      | Self::FullyApproved(_) => "fully_approved",
+      | Self::Scheduled(_) => "scheduled",
+      | Self::Cancelled(_) => "cancelled",
+      | Self::Errored(_) => "errored",
+      | Self::Poisoned(_) => "poisoned",
+      | Self::Done(_) => "done",
+    }
+  }
+
+  /// The job the event happened to, in whatever state it's now in
+  pub fn job(&self) -> Job<States> {
+    fn norm<S: State>(job: &Job<S>) -> Job<States> {
+      job.map_state(|s| s.into_states())
+    }
+
+    match *self {
+      | Self::Created(job) | Self::Approved(job, _) => norm(job),
+      | Self::FullyApproved(job) => norm(job),
+      | Self::Scheduled(job) => norm(job),
+      | Self::Cancelled(job) => norm(job),
+      | Self::Errored(job) => norm(job),
+      | Self::Poisoned(job) => norm(job),
+      | Self::Done(job) => norm(job),
+    }
+  }
+}
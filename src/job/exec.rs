@@ -5,6 +5,9 @@ use crate::job;
 /// Implementation
 pub mod r#impl;
 
+/// Pre- and post-merge hook commands
+pub mod hook;
+
 /// Execute errors
 #[derive(Copy, Clone, Debug)]
 pub enum Error {}
@@ -18,4 +21,7 @@ pub type Result<T> = core::result::Result<T, Error>;
 /// As such, all errors are stored on the job rather than returned eagerly
 pub trait Executor: 'static + Sync + Send + std::fmt::Debug {
   fn schedule_exec(&self, job: &Job<job::StateApproved>);
+
+  /// Execute a scheduled job once its time comes, unless it's been cancelled by then
+  fn schedule_exec_later(&self, job: &Job<job::StateScheduled>);
 }
@@ -0,0 +1,145 @@
+use std::{io::Read,
+          path::Path,
+          process::{Command, Stdio},
+          sync::{Arc, Mutex},
+          thread,
+          time::{Duration, Instant}};
+
+use serde::{Deserialize as De, Serialize as Ser};
+
+use crate::{git::Output, mutex_extra::lock_discard_poison};
+
+/// When a hook runs relative to the merge
+#[derive(Ser, De, PartialEq, Clone, Copy, Debug)]
+pub enum Stage {
+  /// Before merging, with `base` checked out
+  PreMerge,
+  /// After merging and before pushing, with `target` checked out
+  PostMerge,
+}
+
+impl std::fmt::Display for Stage {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      | Self::PreMerge => write!(f, "pre-merge"),
+      | Self::PostMerge => write!(f, "post-merge"),
+    }
+  }
+}
+
+/// Hook errors
+#[derive(Ser, De, PartialEq, Clone, Debug)]
+pub enum Error {
+  /// Couldn't spawn the hook command
+  CouldNotSpawn(String, String),
+  /// Hook command was still running after the timeout, so it was killed.
+  /// Includes any stderr written before then.
+  TimedOut(String, Output),
+  /// Hook command exited not OK with this stderr
+  Failed(String, Output),
+}
+
+/// Hook result
+pub type Result<T> = core::result::Result<T, self::Error>;
+
+/// Output of a child process pipe, read on a background thread
+/// so that a chatty hook can't fill the pipe and block itself forever.
+struct Captured {
+  buf: Arc<Mutex<Vec<u8>>>,
+  reader: thread::JoinHandle<()>,
+}
+
+impl Captured {
+  fn new(mut pipe: Option<impl Read + Send + 'static>) -> Self {
+    let buf = Arc::new(Mutex::new(Vec::new()));
+    let buf_writer = Arc::clone(&buf);
+
+    let reader = thread::spawn(move || {
+      let mut chunk = [0u8; 4096];
+
+      while let Some(n) = pipe.as_mut().and_then(|p| p.read(&mut chunk).ok()).filter(|n| *n > 0) {
+        lock_discard_poison(&buf_writer).extend_from_slice(&chunk[..n]);
+      }
+    });
+
+    Self { buf, reader }
+  }
+
+  /// Wait for the pipe to close, then yield everything written to it
+  fn finish(self) -> Output {
+    let Captured { buf, reader } = self;
+    reader.join().ok();
+
+    let bytes = lock_discard_poison(&buf);
+    Output::from_bytes(&*bytes)
+  }
+
+  /// Yield whatever has been written to the pipe so far
+  fn snapshot(&self) -> Output {
+    Output::from_bytes(&*lock_discard_poison(&self.buf))
+  }
+}
+
+/// Run a hook command with `sh -c` in `dir`,
+/// killing it if it runs longer than `timeout`.
+///
+/// Yields the command's stdout if it exited OK.
+pub fn run(dir: impl AsRef<Path>, command: &str, timeout: Duration) -> Result<Output> {
+  let spawn_failed = |e: std::io::Error| Error::CouldNotSpawn(command.to_string(), format!("{:#?}", e));
+
+  log::info!("executing hook `{}`", command);
+
+  let mut child = Command::new("sh").args(["-c", command])
+                                    .current_dir(dir)
+                                    .stdin(Stdio::null())
+                                    .stdout(Stdio::piped())
+                                    .stderr(Stdio::piped())
+                                    .spawn()
+                                    .map_err(spawn_failed)?;
+
+  let stdout = Captured::new(child.stdout.take());
+  let stderr = Captured::new(child.stderr.take());
+
+  let started = Instant::now();
+
+  loop {
+    match child.try_wait().map_err(spawn_failed)? {
+      | Some(status) if status.success() => return Ok(stdout.finish()),
+      | Some(_) => return Err(Error::Failed(command.to_string(), stderr.finish())),
+      | None if started.elapsed() >= timeout => {
+        log::error!("hook `{}` timed out after {}s", command, timeout.as_secs());
+        child.kill().ok();
+        child.wait().ok();
+
+        // anything the hook spawned may still be holding the pipes open,
+        // so don't wait for them to close
+        return Err(Error::TimedOut(command.to_string(), stderr.snapshot()));
+      },
+      | None => thread::sleep(Duration::from_millis(50)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn run_ok() {
+    let out = run(".", "echo foo", Duration::from_secs(5)).unwrap();
+    assert_eq!(out.as_ref(), "foo\n");
+  }
+
+  #[test]
+  fn run_failed() {
+    let err = run(".", "echo oh no >&2; exit 3", Duration::from_secs(5)).unwrap_err();
+    assert_eq!(err,
+               Error::Failed("echo oh no >&2; exit 3".into(), Output::from_bytes("oh no\n")));
+  }
+
+  #[test]
+  fn run_timed_out() {
+    let err = run(".", "sleep 5", Duration::from_millis(100)).unwrap_err();
+    assert!(matches!(err, Error::TimedOut(_, _)));
+  }
+}
@@ -4,7 +4,8 @@ use std::{sync::{Condvar, Mutex, MutexGuard},
 
 use chrono::Utc;
 
-use crate::{git, job, job::Job, mutex_extra::lock_discard_poison};
+use super::hook;
+use crate::{deploy, git, job, job::Job, mutex_extra::lock_discard_poison};
 
 /// Initialize executor worker thread
 pub fn init(jobs: Box<dyn job::Store>, git: Box<dyn crate::git::Client>) {
@@ -14,6 +15,19 @@ pub fn init(jobs: Box<dyn job::Store>, git: Box<dyn crate::git::Client>) {
     WORKER = Some(std::thread::spawn(worker));
   }
 
+  // pick back up any work left over from before a restart,
+  // except for jobs waiting on a lock, which are queued again when it's lifted
+  jobs.get_all_approved().into_iter().map(Work::New).for_each(Work::queue);
+  jobs.get_all_errored()
+      .into_iter()
+      .map(Work::Retry)
+      .for_each(Work::queue);
+  jobs.get_all_scheduled()
+      .into_iter()
+      .filter(|j| !j.state.held_by_lock())
+      .map(Work::Scheduled)
+      .for_each(Work::queue);
+
   *lock_discard_poison(&JOB_STORE) = Some(jobs);
   *lock_discard_poison(&GIT_CLIENT) = Some(git);
 }
@@ -39,6 +53,7 @@ lazy_static::lazy_static! {
 enum Work {
   New(Job<job::StateApproved>),
   Retry(Job<job::StateErrored>),
+  Scheduled(Job<job::StateScheduled>),
 }
 
 impl Work {
@@ -48,28 +63,138 @@ impl Work {
     match self {
       | Self::New(j) => j.map_state(|s| s.into_states()),
       | Self::Retry(j) => j.map_state(|s| s.into_states()),
+      | Self::Scheduled(j) => j.map_state(|s| s.into_states()),
     }
   }
 
   fn time_til(&self) -> Duration {
+    let til = |at: chrono::DateTime<Utc>| at.signed_duration_since(Utc::now()).to_std().unwrap_or_default();
+
     match self {
-      | Self::Retry(job) => job.state
-                               .next_attempt
-                               .signed_duration_since(Utc::now())
-                               .to_std()
-                               .unwrap_or_default(),
+      | Self::Retry(job) => til(job.state.next_attempt),
+      | Self::Scheduled(job) => til(job.state.at),
       | Self::New(_) => Duration::default(),
     }
   }
 
+  /// Scheduled jobs may have been cancelled while waiting
+  fn cancelled(&self) -> bool {
+    match self {
+      | Self::Scheduled(job) => lock_discard_poison(&JOB_STORE).as_ref()
+                                                               .and_then(|jobs| jobs.get_scheduled(&job.id))
+                                                               .is_none(),
+      | _ => false,
+    }
+  }
+
   fn queue(self) {
-    let q = &mut *lock_discard_poison(&QUEUE);
-    q.push(self);
+    lock_discard_poison(&QUEUE).push(self);
 
+    // hold the lock so the worker can't miss this between checking the queue and waiting
+    let _lock = lock_discard_poison(&WORK_QUEUED.0);
     WORK_QUEUED.1.notify_all();
   }
 }
 
+/// Ensure the commit about to be deployed from `env.base`
+/// is the one that was last deployed to the previous stage of the app's promotion chain.
+///
+/// If we don't remember the previous stage being deployed (e.g. since a restart),
+/// fall back to the commit we last pushed to the previous stage's target,
+/// so commits pushed there by hand since don't count as promoted.
+fn check_promoted<S: job::State>(jobs: &dyn job::Store,
+                                 repo: &dyn git::RepoContext,
+                                 job: &Job<S>,
+                                 app_repo: &deploy::Repo,
+                                 env: &deploy::Mergeable,
+                                 base: &git::Sha)
+                                 -> Result<(), job::Error> {
+  let prev = match job.app.prev_stage(&job.command.env_name) {
+    | Some(prev) => prev,
+    | None => return Ok(()),
+  };
+
+  let not_promoted = || job::Error::NotPromoted(app_repo.name.clone(), prev.to_string(), base.clone());
+  let git_failed = |e| job::Error::Git(app_repo.name.clone(), e);
+
+  let deployed = jobs.get_last_done(&job.app.team_id, &job.app.name, prev)
+                     .and_then(|done| {
+                       done.state
+                           .merged()
+                           .iter()
+                           .find(|m| m.repo == app_repo.name)
+                           .map(|m| m.target.clone())
+                     });
+
+  let deployed = match (deployed, app_repo.environments.iter().find(|e| e.name_eq(prev))) {
+    | (Some(sha), _) => sha,
+    | (None, Some(prev_env)) => {
+      repo.switch(&prev_env.target).map_err(git_failed)?;
+      let sha = repo.last_pushed(&prev_env.target).map_err(git_failed)?;
+
+      repo.switch(&env.base).map_err(git_failed)?;
+      sha.ok_or_else(not_promoted)?
+    },
+    | (None, None) => return Err(not_promoted()),
+  };
+
+  match &deployed == base {
+    | true => Ok(()),
+    | false => Err(not_promoted()),
+  }
+}
+
+/// Merge `env.base` into `env.target` in a single repo,
+/// running the environment's hooks along the way
+fn merge<S: job::State>(jobs: &dyn job::Store,
+                        git: &dyn git::Client,
+                        job: &Job<S>,
+                        app_repo: &deploy::Repo,
+                        env: &deploy::Mergeable)
+                        -> Result<job::Merged, job::Error> {
+  let git_failed = |e| job::Error::Git(app_repo.name.clone(), e);
+
+  let run_hooks = |repo: &dyn git::RepoContext, stage: hook::Stage, cmds: &[String]| {
+    let failed = |e| job::Error::Hook(app_repo.name.clone(), stage, e);
+
+    cmds.iter().try_for_each(|cmd| {
+                 hook::run(repo.workdir(), cmd, env.hook_timeout()).map(|_| ())
+                                                                   .map_err(failed)
+               })
+  };
+
+  // clone into app_repo, e.g. mergebot_frontend
+  let repo = git.repo(&app_repo.url, &format!("{}_{}", job.app.name, app_repo.name))
+                .map_err(git_failed)?;
+
+  repo.fetch_all().map_err(git_failed)?;
+
+  repo.switch(&env.base).map_err(git_failed)?;
+  repo.update_branch().map_err(git_failed)?;
+  let base = repo.sha(&env.base).map_err(git_failed)?;
+  check_promoted(jobs, repo.as_ref(), job, app_repo, env, &base)?;
+  run_hooks(repo.as_ref(), hook::Stage::PreMerge, &env.pre_merge)?;
+
+  repo.switch(&env.target).map_err(git_failed)?;
+  repo.update_branch().map_err(git_failed)?;
+  let prev_target = repo.sha(&env.target).map_err(git_failed)?;
+
+  repo.merge(&env.base).map_err(git_failed)?;
+  run_hooks(repo.as_ref(), hook::Stage::PostMerge, &env.post_merge)?;
+  let target = repo.sha(&env.target).map_err(git_failed)?;
+
+  repo.push().map_err(git_failed)?;
+
+  // a changelog is nice to have, but not worth failing a deploy that's already been pushed
+  let commits = repo.log(&prev_target, &target).unwrap_or_default();
+
+  Ok(job::Merged { repo: app_repo.name.clone(),
+                   prev_target,
+                   target,
+                   at: Utc::now(),
+                   commits })
+}
+
 fn exec<S: job::State>(job: &Job<S>) {
   // trust someone above us to make sure these are set before a job gets here
   let jobs_lock = lock_discard_poison(&JOB_STORE);
@@ -78,36 +203,62 @@ fn exec<S: job::State>(job: &Job<S>) {
   let jobs = jobs_lock.as_ref().unwrap();
   let git = git_lock.as_ref().unwrap();
 
-  let errs = job.app
-                .repos
-                .iter()
-                .map(|app_repo| {
-                  let env = app_repo.environments
-                                    .iter()
-                                    .find(|env| env.name_eq(&job.command.env_name))
-                                    .expect("Environment was already matched against command");
-
-                  // clone into app_repo, e.g. mergebot_frontend
-                  git.repo(&app_repo.url, &format!("{}_{}", job.app.name, app_repo.name))
-                     .and_then(|repo| {
-                       repo.fetch_all()?;
-
-                       repo.switch(&env.base)?;
-                       repo.update_branch()?;
-
-                       repo.switch(&env.target)?;
-                       repo.update_branch()?;
-
-                       repo.merge(&env.base)?;
-
-                       repo.push()
-                     })
-                })
-                .filter_map(|r| r.err().map(job::Error::Git))
-                .collect::<Vec<_>>();
+  let merge_all = || {
+    job.app
+       .repos
+       .iter()
+       .map(|app_repo| {
+         let env = app_repo.environments
+                           .iter()
+                           .find(|env| env.name_eq(&job.command.env_name))
+                           .expect("Environment was already matched against command");
+
+         merge(jobs.as_ref(), git.as_ref(), job, app_repo, env)
+       })
+       .collect::<Vec<_>>()
+  };
+
+  // windows, freezes or locks may have changed since the job was created
+  let allowed = match job.command.override_freeze {
+    | true => Ok(()),
+    | false => job.app.allowed_at(&job.command.env_name, Utc::now()),
+  };
+
+  // overriding a freeze doesn't get around a lock
+  let allowed = allowed.and_then(|_| match jobs.get_lock(&job.app.team_id, &job.app.name, &job.command.env_name) {
+                                   | Some(lock) => Err(deploy::Blocked::Locked(Box::new(lock))),
+                                   | None => Ok(()),
+                                 });
+
+  let results = match allowed {
+    | Ok(()) => merge_all(),
+    | Err(blocked @ deploy::Blocked::Locked(_)) => {
+      // locks don't expire, so hold the job until someone runs `/deploy unlock`
+      log::info!("job {:?}: blocked ({}), holding until unlocked", job.id, blocked);
+      jobs.postponed(&job.id, Utc::now(), blocked);
+      return;
+    },
+    | Err(blocked) => match blocked.until(Utc::now()) {
+      | Some(at) => {
+        // not a failure, so wait for the block to lift rather than burning retries on it
+        log::info!("job {:?}: blocked ({}), postponing until {}", job.id, blocked, at);
+        jobs.postponed(&job.id, at, blocked);
+        return;
+      },
+      | None => {
+        // retrying won't help, so give up on the job right away
+        jobs.state_errored(&job.id, vec![job::Error::Blocked(blocked)]);
+        jobs.state_poisoned(&job.id);
+        return;
+      },
+    },
+  };
+
+  let (merged, errs): (Vec<_>, Vec<_>) = results.into_iter().partition(|r| r.is_ok());
+  let errs = errs.into_iter().filter_map(|r| r.err()).collect::<Vec<_>>();
 
   if errs.is_empty() {
-    jobs.state_done(&job.id);
+    jobs.state_done(&job.id, merged.into_iter().filter_map(|r| r.ok()).collect());
   } else {
     jobs.state_errored(&job.id, errs);
 
@@ -128,51 +279,57 @@ impl super::Executor for Executor {
     let work = Work::New(job.clone());
     work.queue();
   }
+
+  fn schedule_exec_later(&self, job: &Job<job::StateScheduled>) {
+    let work = Work::Scheduled(job.clone());
+    work.queue();
+  }
 }
 
-/// Pull work out of the work queue
-fn get_work() -> Option<(Work, Duration)> {
+/// Pull the work to be done soonest out of the work queue if it's due,
+/// otherwise yield how long until it will be.
+fn get_work() -> Option<Result<Work, Duration>> {
   let mut q: MutexGuard<'_, Vec<Work>> = lock_discard_poison(&QUEUE);
-  let mut work = (*q).iter()
-                     .enumerate()
-                     .map(|(ix, w)| (ix, w.time_til()))
-                     .collect::<Vec<_>>();
-
-  work.sort_by_key(|(_, dur)| *dur);
-
-  // Yield the work to be done soonest
-  work.get(0).map(|&(ix, dur)| ((*q).remove(ix), dur))
+  let soonest = (*q).iter()
+                    .enumerate()
+                    .map(|(ix, w)| (ix, w.time_til()))
+                    .min_by_key(|(_, dur)| *dur);
+
+  soonest.map(|(ix, dur)| match dur.is_zero() {
+           | true => Ok((*q).remove(ix)),
+           | false => Err(dur),
+         })
 }
 
 /// Worker thread logic
 fn worker() {
-  use std::thread::sleep;
-
   std::sync::Arc::clone(&crate::APP_INIT).wait();
 
   loop {
-    if let Some((work, time_til)) = get_work() {
-      log::info!("job {:?}: work picked", work.job().id);
-
-      if !time_til.is_zero() {
-        // REVISIT: if this wait is long, fresh work
-        //          will be blocked until done waiting
-        log::info!("job {:?}: waiting for {}ms to retry",
-                   work.job().id,
-                   time_til.as_millis());
-        sleep(time_til);
-      }
-
-      log::info!("job {:?}: working", work.job().id);
-      exec(&work.job());
-    } else {
-      log::info!("worker thread awake and waiting for work");
-
-      let lock = lock_discard_poison(&WORK_QUEUED.0);
-
-      let work_queued = WORK_QUEUED.1.wait(lock).unwrap();
-
-      drop(work_queued);
+    // Waiting on the condvar rather than sleeping means
+    // fresh work isn't blocked by work scheduled for later
+    let lock = lock_discard_poison(&WORK_QUEUED.0);
+
+    match get_work() {
+      | Some(Ok(work)) => {
+        drop(lock);
+        log::info!("job {:?}: work picked", work.job().id);
+
+        if work.cancelled() {
+          log::info!("job {:?}: cancelled, skipping", work.job().id);
+        } else {
+          log::info!("job {:?}: working", work.job().id);
+          exec(&work.job());
+        }
+      },
+      | Some(Err(time_til)) => {
+        log::info!("worker thread waiting {}ms for next work", time_til.as_millis());
+        drop(WORK_QUEUED.1.wait_timeout(lock, time_til));
+      },
+      | None => {
+        log::info!("worker thread awake and waiting for work");
+        drop(WORK_QUEUED.1.wait(lock));
+      },
     }
   }
 }
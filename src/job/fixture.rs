@@ -0,0 +1,46 @@
+//! Jobs for tests. Json here is much more concise than struct initializers.
+
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use super::{Id, Job, State};
+
+/// A repo named `name` at `foo.com/my/{name}`, whose `prod` environment merges `staging` into `prod`
+pub fn repo(name: &str, users: Value) -> Value {
+  json!({
+    "url": format!("git@foo.com:my/{}", name),
+    "human_url": format!("foo.com/my/{}", name),
+    "name": name,
+    "environments": [
+      {
+        "name": "prod",
+        "base": "staging",
+        "target": "prod",
+        "users": users
+      }
+    ]
+  })
+}
+
+/// A job in `state` that <@U123> created at 2021-10-20 12:00 UTC to deploy `my_app` to `prod`
+pub fn job<S: State + DeserializeOwned>(state: Value, repos: Vec<Value>) -> Job<S> {
+  let job = json!({
+    "id": Id::new(),
+    "state": state,
+    "command": {
+      "app_name": "my_app",
+      "env_name": "prod",
+      "user_id": "U123",
+      "team_id": "T123"
+    },
+    "app": {
+      "name": "my_app",
+      "team_id": "T123",
+      "notification_channel_id": "C123",
+      "repos": repos
+    },
+    "created_at": "2021-10-20T12:00:00Z"
+  });
+
+  serde_json::from_value(job).unwrap()
+}
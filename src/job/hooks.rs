@@ -1,23 +1,151 @@
+use std::{sync::{mpsc, Mutex},
+          time::Duration};
+
 use super::event::*;
-use crate::result_extra::*;
+use crate::{mutex_extra::lock_discard_poison, result_extra::*, slack};
+
+/// How many times a notification slack rate limits is sent again before it's dropped
+const MAX_REDELIVERIES: u32 = 5;
+
+/// Longest the outbox waits before sending a rate limited notification again, whatever slack asks
+const MAX_REDELIVERY_WAIT: Duration = Duration::from_secs(60);
+
+/// A notification waiting in the outbox
+struct Notification {
+  /// What's being sent, for logs
+  what: String,
+  send: Box<dyn FnMut() -> slack::Result<()> + Send>,
+}
+
+lazy_static::lazy_static! {
+  /// Queue of notifications for the outbox thread, which is started the first time one is queued
+  static ref OUTBOX: Mutex<mpsc::Sender<Notification>> = {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || deliver(rx));
+    Mutex::new(tx)
+  };
+}
+
+/// Outbox thread logic. Notifications are sent one at a time, in the order they were queued,
+/// so a rate limited notification holds up the ones behind it (which slack would likely rate limit too).
+fn deliver(outbox: mpsc::Receiver<Notification>) {
+  for mut note in outbox {
+    let mut redeliveries = 0;
+
+    loop {
+      match (note.send)() {
+        | Ok(()) => break,
+        | Err(slack::Error::RateLimited(wait)) if redeliveries < MAX_REDELIVERIES => {
+          let wait = wait.min(MAX_REDELIVERY_WAIT);
+          log::warn!("slack rate limited {}, sending it again in {:?}", note.what, wait);
+
+          redeliveries += 1;
+          std::thread::sleep(wait);
+        },
+        | Err(e) => {
+          log::error!("failed to send {} {:?}", note.what, e);
+          break;
+        },
+      }
+    }
+  }
+}
+
+/// Queue a notification to be sent by the outbox thread, so listeners never wait on slack.
+///
+/// If slack rate limits it, the outbox waits as long as slack asks (up to `MAX_REDELIVERY_WAIT`)
+/// and sends it again, up to `MAX_REDELIVERIES` times.
+pub(super) fn notify<T>(what: String, mut send: impl FnMut() -> slack::Result<T> + Send + 'static) {
+  let note = Notification { what,
+                            send: Box::new(move || send().map(|_| ())) };
+
+  if lock_discard_poison(&OUTBOX).send(note).is_err() {
+    log::error!("outbox thread is gone, dropping notification");
+  }
+}
 
 pub fn on_create_notify(state: &'static crate::State) -> Listener {
   let cloj = move |ev: Event| {
     if let Event::Created(job) = ev {
       log::info!("job {:?} created", job.id);
-      state.job_messenger
-           .send_job_created(&job)
-           .tap_err(|e| log::error!("job {:?}: error notifying create {:?}", job.id, e))
-           .tap(|msg_id| {
-             state.jobs.notified(&job.id, msg_id.clone());
-           })
-           .ok();
+      let job = job.clone();
+
+      notify(format!("'job created' message for job {:?}", job.id), move || {
+        state.job_messenger.send_job_created(&job).tap(|msg_id| {
+                                                    state.jobs.notified(&job.id, msg_id.clone());
+                                                  })
+      });
     }
   };
 
   Box::from(cloj)
 }
 
+/// Users who still need to approve a job and want to be DMed about it
+fn approvers_to_dm(state: &'static crate::State, job: &super::Job<super::StateInit>) -> Vec<String> {
+  use crate::deploy::User;
+
+  let mut user_ids = job.outstanding_approvers()
+                        .into_iter()
+                        .filter(User::is_approver)
+                        .flat_map(|user| match user {
+                          | User::User { user_id, .. } => vec![user_id],
+                          | User::Group { group_id, .. } if job.app.dm_groups => {
+                            state.slack_groups
+                                 .expand(&job.app.team_id, &group_id)
+                                 .tap_err(|e| log::error!("job {:?}: couldn't expand group {:?}", job.id, e))
+                                 .unwrap_or_default()
+                          },
+                          | User::Group { .. } => vec![],
+                        })
+                        .filter(|user_id| user_id != &job.command.user_id)
+                        .filter(|user_id| {
+                          state.jobs
+                               .get_notify_prefs(&job.app.team_id, user_id)
+                               .map(|prefs| prefs.dm)
+                               .unwrap_or(true)
+                        })
+                        .collect::<Vec<_>>();
+
+  user_ids.sort();
+  user_ids.dedup();
+  user_ids
+}
+
+/// DM a job's outstanding approvers about it, e.g. `reminder`s from `reminders::start`
+pub(super) fn dm_approvers(state: &'static crate::State, job: &super::Job<super::StateInit>, reminder: bool) {
+  for user_id in approvers_to_dm(state, job) {
+    let job = job.clone();
+
+    notify(format!("approval DM to {} for job {:?}", user_id, job.id), move || {
+      state.job_messenger.send_approval_dm(&job, &user_id, reminder)
+    });
+  }
+}
+
+/// DM approvers when a job is created
+///
+/// (reminder DMs are sent by `reminders::start`)
+pub fn on_create_dm_approvers(state: &'static crate::State) -> Listener {
+  let f = move |ev: Event| {
+    if let Event::Created(job) = ev {
+      let id = job.id.clone();
+
+      // the request message (which the DMs link to) is queued by another listener of this event,
+      // so queue working out who to DM behind it to see the job once it knows the message's id
+      notify(format!("approver DMs for job {:?}", id), move || {
+        if let Some(job) = state.jobs.get_new(&id) {
+          dm_approvers(state, &job, false);
+        }
+
+        Ok(())
+      });
+    }
+  };
+
+  Box::from(f)
+}
+
 /// On approval, check if fully approved, change state, and log
 pub fn on_full_approval_change_state(state: &'static crate::State) -> Listener {
   let cloj = move |ev: Event| {
@@ -44,17 +172,33 @@ pub fn on_full_approval_change_state(state: &'static crate::State) -> Listener {
   Box::from(cloj)
 }
 
+/// Reply in the job's thread when someone approves
+pub fn on_approval_notify(state: &'static crate::State) -> Listener {
+  let f = move |ev: Event| match ev {
+    | Event::Approved(job, user) => {
+      let (job, user) = (job.clone(), user.clone());
+
+      notify(format!("'job approved by' message for job {:?}", job.id), move || {
+        state.job_messenger.send_job_approved_by(&job, &user)
+      });
+    },
+    | _ => (),
+  };
+
+  Box::from(f)
+}
+
 /// Send message on full approval
 pub fn on_full_approval_notify(state: &'static crate::State) -> Listener {
   let f = move |ev: Event| match ev {
     | Event::FullyApproved(job) => {
       log::info!("job {:?}: sending approval message...", job.id);
 
-      if let Err(e) = state.job_messenger.send_job_approved(&job) {
-        log::error!("{:#?}", e);
-      }
+      let job = job.clone();
 
-      log::info!("job {:?}: approval message sent", job.id);
+      notify(format!("'job approved' message for job {:?}", job.id), move || {
+        state.job_messenger.send_job_approved(&job)
+      });
     },
     | _ => (),
   };
@@ -62,12 +206,73 @@ pub fn on_full_approval_notify(state: &'static crate::State) -> Listener {
   Box::from(f)
 }
 
-/// Deploy on full approval
+/// Deploy on full approval, or schedule the deploy if it was requested for later
 pub fn on_full_approval_deploy(state: &'static crate::State) -> Listener {
   let f = move |ev: Event| match ev {
+    | Event::FullyApproved(job)
+      if job.command
+            .scheduled_for
+            .filter(|at| at > &chrono::Utc::now())
+            .is_some() =>
+    {
+      log::info!("job {:?}: scheduling", job.id);
+      let id = job.id.clone();
+
+      std::thread::spawn(move || {
+        state.jobs.state_scheduled(&id);
+      });
+    },
     | Event::FullyApproved(job) => {
       log::info!("job {:?}: deploying", job.id);
-      state.job_executor.schedule_exec(&job);
+      state.job_executor.schedule_exec(job);
+    },
+    | _ => (),
+  };
+
+  Box::from(f)
+}
+
+/// Deploy scheduled jobs once their time comes
+pub fn on_scheduled_deploy(state: &'static crate::State) -> Listener {
+  let f = move |ev: Event| match ev {
+    | Event::Scheduled(job) if job.state.held_by_lock() => {
+      log::info!("job {:?}: holding until unlocked", job.id);
+    },
+    | Event::Scheduled(job) => {
+      log::info!("job {:?}: deploying at {}", job.id, job.state.at);
+      state.job_executor.schedule_exec_later(job);
+    },
+    | _ => (),
+  };
+
+  Box::from(f)
+}
+
+/// Send message when a job is scheduled
+pub fn on_scheduled_notify(state: &'static crate::State) -> Listener {
+  let f = move |ev: Event| match ev {
+    | Event::Scheduled(j) => {
+      let j = j.clone();
+
+      notify(format!("'job scheduled' message for job {:?}", j.id), move || {
+        state.job_messenger.send_job_scheduled(&j)
+      });
+    },
+    | _ => (),
+  };
+
+  Box::from(f)
+}
+
+/// Send message when a scheduled job is cancelled
+pub fn on_cancel_notify(state: &'static crate::State) -> Listener {
+  let f = move |ev: Event| match ev {
+    | Event::Cancelled(j) => {
+      let j = j.clone();
+
+      notify(format!("'job cancelled' message for job {:?}", j.id), move || {
+        state.job_messenger.send_job_cancelled(&j)
+      });
     },
     | _ => (),
   };
@@ -80,7 +285,7 @@ pub fn on_failure_poison(state: &'static crate::State) -> Listener {
   let f = move |ev: Event| match ev {
     | Event::Errored(j) => {
       let errs = j.flatten_errors();
-      if errs.len() > 4 {
+      if errs.len() >= 4 {
         log::error!("job {:?} poisoned!!1", j.id);
         let id = j.id.clone();
 
@@ -111,9 +316,11 @@ pub fn on_failure_log(_: &'static crate::State) -> Listener {
 pub fn on_poison_notify(state: &'static crate::State) -> Listener {
   let f = move |ev: Event| match ev {
     | Event::Poisoned(j) => {
-      if let Err(e) = state.job_messenger.send_job_failed(&j) {
-        log::error!("job {:?}: failed to send 'job failed' message {:?}", j.id, e);
-      }
+      let j = j.clone();
+
+      notify(format!("'job failed' message for job {:?}", j.id), move || {
+        state.job_messenger.send_job_failed(&j)
+      });
     },
     | _ => (),
   };
@@ -125,8 +332,27 @@ pub fn on_poison_notify(state: &'static crate::State) -> Listener {
 pub fn on_done_notify(state: &'static crate::State) -> Listener {
   let f = move |ev: Event| match ev {
     | Event::Done(j) => {
-      if let Err(e) = state.job_messenger.send_job_done(&j) {
-        log::error!("job {:?}: failed to send 'job done' message {:?}", j.id, e);
+      let j = j.clone();
+
+      notify(format!("'job done' message for job {:?}", j.id), move || {
+        state.job_messenger.send_job_done(&j)
+      });
+    },
+    | _ => (),
+  };
+
+  Box::from(f)
+}
+
+/// If done, cross-post to the environment's announcement channel
+pub fn on_done_announce(state: &'static crate::State) -> Listener {
+  let f = move |ev: Event| match ev {
+    | Event::Done(j) => {
+      if let Some(channel_id) = j.app.announcement_channel(&j.command.env_name) {
+        let (j, channel_id) = (j.clone(), channel_id.to_string());
+
+        notify(format!("announcement in {} for job {:?}", channel_id, j.id),
+               move || state.job_messenger.send_job_announcement(&j, &channel_id));
       }
     },
     | _ => (),
@@ -134,3 +360,89 @@ pub fn on_done_notify(state: &'static crate::State) -> Listener {
 
   Box::from(f)
 }
+
+/// Keep the original request message up to date with the job's status
+pub fn on_change_update_status(state: &'static crate::State) -> Listener {
+  let f = move |ev: Event| {
+    let job = ev.job();
+
+    // jobs are created before their request message is sent
+    if job.state.msg_id().is_none() {
+      return;
+    }
+
+    notify(format!("request message update for job {:?}", job.id), move || {
+      state.job_messenger.update_job_status(&job)
+    });
+  };
+
+  Box::from(f)
+}
+
+/// Send lifecycle messages to the other places the job's app asks to be notified
+pub fn on_change_notify_sinks(state: &'static crate::State) -> Listener {
+  let f = move |ev: Event| {
+    let job = ev.job();
+
+    // jobs don't keep their app's sinks (see `deploy::App::sinks`), so find them in its current config
+    let sinks = match state.app_reader.get_app(&job.app.team_id, &job.app.name) {
+      | Ok(app) => app.sinks,
+      | Err(e) => {
+        log::error!("job {:?}: couldn't read sinks {:?}", job.id, e);
+        return;
+      },
+    };
+
+    if sinks.is_empty() {
+      return;
+    }
+
+    if let Some(note) = crate::notify::Note::from_event(&ev) {
+      // webhooks and SMTP servers can be slow, and shouldn't hold up other listeners
+      std::thread::spawn(move || {
+        for sink in &sinks {
+          if let Err(e) = state.notifier.notify(&job.app, sink, &note) {
+            log::error!("job {:?}: failed to notify {:?} {:?}", job.id, sink, e);
+          }
+        }
+      });
+    }
+  };
+
+  Box::from(f)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{atomic::{AtomicU32, Ordering},
+                  Arc};
+
+  use super::*;
+
+  #[test]
+  fn outbox_redelivers_rate_limited() {
+    let attempts = Arc::new(AtomicU32::new(0));
+    let (tx, rx) = mpsc::channel();
+
+    let sent = Arc::clone(&attempts);
+    tx.send(Notification { what: "limited".into(),
+                           send: Box::new(move || match sent.fetch_add(1, Ordering::SeqCst) {
+                             | 0 | 1 => Err(slack::Error::RateLimited(Duration::from_millis(1))),
+                             | _ => Ok(()),
+                           }) })
+      .unwrap();
+
+    let sent = Arc::clone(&attempts);
+    tx.send(Notification { what: "always limited".into(),
+                           send: Box::new(move || {
+                             sent.fetch_add(1, Ordering::SeqCst);
+                             Err(slack::Error::RateLimited(Duration::from_millis(1)))
+                           }) })
+      .unwrap();
+
+    drop(tx);
+    deliver(rx);
+
+    assert_eq!(attempts.load(Ordering::SeqCst), 3 + 1 + MAX_REDELIVERIES);
+  }
+}
@@ -1,22 +1,54 @@
 use super::*;
 use crate::{deploy, job, slack};
 
+/// Changelogs longer than this are attached to the done message as a snippet rather than inlined
+const MAX_CHANGELOG_LEN: usize = 2000;
+
 /// A messenger is able to notify the approvers of an app of a deployment
 pub trait Messenger: 'static + Sync + Send + std::fmt::Debug {
   /// Notify approvers of an app for deployment
   fn send_job_created(&self, job: &Job<job::StateInit>) -> slack::Result<slack::msg::Id>;
 
+  /// DM an approver that the job needs their approval, or remind them that it still does
+  fn send_approval_dm(&self, job: &Job<job::StateInit>, user_id: &str, reminder: bool)
+                      -> slack::Result<slack::msg::Id>;
+
+  /// Remind outstanding approvers in the job's thread that it's waiting on them
+  fn send_approval_reminder(&self, job: &Job<job::StateInit>) -> slack::Result<slack::msg::Id>;
+
+  /// Notify that someone approved the job, and who the job is still waiting on
+  fn send_job_approved_by(&self, job: &Job<job::StateInit>, user: &deploy::User) -> slack::Result<slack::msg::Id>;
+
   /// Notify that the job has been approved
   fn send_job_approved(&self, job: &Job<job::StateApproved>) -> slack::Result<slack::msg::Id>;
 
-  /// Notify that the job has failed
+  /// Notify that the job has failed, with the errors from every attempt
   fn send_job_failed(&self, job: &Job<job::StatePoisoned>) -> slack::Result<slack::msg::Id>;
 
   /// Notify that the job has been executed
   fn send_job_done(&self, job: &Job<job::StateDone>) -> slack::Result<slack::msg::Id>;
+
+  /// Announce that the job has been executed in a channel besides the request's
+  fn send_job_announcement(&self, job: &Job<job::StateDone>, channel_id: &str) -> slack::Result<slack::msg::Id>;
+
+  /// Notify that the job will be executed later
+  fn send_job_scheduled(&self, job: &Job<job::StateScheduled>) -> slack::Result<slack::msg::Id>;
+
+  /// Notify that the scheduled job was cancelled
+  fn send_job_cancelled(&self, job: &Job<job::StateCancelled>) -> slack::Result<slack::msg::Id>;
+
+  /// Rewrite the original request message with the job's current status
+  fn update_job_status(&self, job: &Job<job::States>) -> slack::Result<slack::msg::Id>;
+}
+
+/// Format a time so that slack displays it in the reader's timezone
+pub(super) fn fmt_time(at: &DateTime<Utc>) -> String {
+  format!("<!date^{}^{{date_short_pretty}} at {{time}}|{}>",
+          at.timestamp(),
+          at.format("%Y-%m-%d %H:%M UTC"))
 }
 
-fn fmt_approvers(approvers: &[deploy::app::User]) -> String {
+pub(super) fn fmt_approvers(approvers: &[deploy::app::User]) -> String {
   if approvers.len() == 1 {
     let usr = approvers.get(0).unwrap();
     return usr.to_at();
@@ -92,10 +124,15 @@ fn job_created_msg(job: &Job<job::StateInit>) -> Vec<slack_blocks::Block<'static
                (changes, ctas)
              });
 
+  let scheduled = job.command
+                     .scheduled_for
+                     .map(|at| format!(" at {}", fmt_time(&at)))
+                     .unwrap_or_default();
+
   let mut blocks = vec![
     blox! {
       <section_block>
-        <text kind=mrkdwn>{format!("<!here> <@{}> has requested a deploy merge for {} to {}.", job.command.user_id, job.app.name, job.command.env_name)}</text>
+        <text kind=mrkdwn>{format!("<!here> <@{}> has requested a deploy merge for {} to {}{}.", job.command.user_id, job.app.name, job.command.env_name, scheduled)}</text>
       </section_block>
     }.into(),
   ];
@@ -106,14 +143,213 @@ fn job_created_msg(job: &Job<job::StateInit>) -> Vec<slack_blocks::Block<'static
   blocks
 }
 
+/// The original request message, followed by the job's current status
+fn job_status_msg(job: &Job<job::States>) -> Vec<slack_blocks::Block<'static>> {
+  use slack_blocks::{blox::*, Block};
+
+  let mut blocks = job_created_msg(&job.map_state(|s| s.init().clone()));
+
+  let mut status = vec![format!("*Status:* {}", job::status::summarize(job))];
+
+  let approved_by = &job.state.init().approved_by;
+  if !approved_by.is_empty() {
+    status.push(format!("*Approved by:* {}", fmt_approvers(approved_by)));
+  }
+
+  if let job::States::Done(done) = &job.state {
+    status.extend(done.merged().iter().map(|m| {
+                                        format!("*{}:* merged `{}..{}`",
+                                                m.repo,
+                                                m.prev_target.short(),
+                                                m.target.short())
+                                      }));
+  }
+
+  blocks.extend(status.into_iter().map(|line| -> Block {
+                                    blox! {<section_block><text kind=mrkdwn>{line}</text></section_block>}.into()
+                                  }));
+
+  blocks
+}
+
+/// Every attempt's errors, oldest first
+fn job_failed_msg(job: &Job<job::StatePoisoned>) -> Vec<slack_blocks::Block<'static>> {
+  use slack_blocks::{blox::*, Block};
+
+  let section =
+    |body: String| -> Block { blox! {<section_block><text kind=mrkdwn>{body}</text></section_block>}.into() };
+
+  let mut attempts = job.map_state(|s| s.prev).flatten_errors();
+  attempts.reverse();
+
+  let mut blocks = vec![section(format!("Merge failed after {} attempts :skull_and_crossbones:", attempts.len()))];
+
+  for (ix, attempt) in attempts.iter().enumerate() {
+    blocks.push(section(format!("*Attempt {}* failed {}:", ix + 1, fmt_time(&attempt.at))));
+    blocks.extend(attempt.errs.iter().map(|e| section(e.to_string())));
+  }
+
+  blocks
+}
+
+/// Each repo's merged commits, linking to pull requests if `mrkdwn`
+fn changelog(job: &Job<job::StateDone>, mrkdwn: bool) -> String {
+  job.state
+     .merged()
+     .iter()
+     .filter(|m| !m.commits.is_empty())
+     .map(|m| {
+       let repo_url = job.app
+                         .repos
+                         .iter()
+                         .find(|r| r.name == m.repo)
+                         .map(|r| r.human_url.trim_end_matches('/'));
+
+       let commits =
+         m.commits.iter().map(|c| {
+                           let pr = match (c.pr, repo_url) {
+                             | (Some(pr), Some(url)) if mrkdwn => format!(" (<{}/pull/{}|#{}>)", url, pr, pr),
+                             | (Some(pr), _) => format!(" (#{})", pr),
+                             | (None, _) => String::new(),
+                           };
+
+                           format!("• {}{} - {}", c.subject, pr, c.author)
+                         });
+
+       let title = match mrkdwn {
+         | true => format!("*{}*", m.repo),
+         | false => m.repo.clone(),
+       };
+
+       std::iter::once(title).chain(commits).collect::<Vec<_>>().join("\n")
+     })
+     .collect::<Vec<_>>()
+     .join("\n\n")
+}
+
+/// What was deployed, by whom, linking to the request if we can
+fn job_announcement_msg(job: &Job<job::StateDone>) -> Vec<slack_blocks::Block<'static>> {
+  use slack_blocks::{blox::*, Block};
+
+  let request = match job.state.clone().into_states().msg_id() {
+    | Some(id) if !job.command.team_domain.is_empty() => {
+      format!(" (<{}|request>)", id.permalink(&job.command.team_domain))
+    },
+    | _ => String::new(),
+  };
+
+  let deployed = format!(":rocket: <@{}> deployed {} to {}{}.",
+                         job.command.user_id, job.app.name, job.command.env_name, request);
+
+  let mut blocks: Vec<Block> = vec![blox! {<section_block><text kind=mrkdwn>{deployed}</text></section_block>}.into()];
+
+  blocks.extend(job.state.merged().iter().map(|m| -> Block {
+                                           let merged = format!("{}: merged `{}..{}`",
+                                                                m.repo,
+                                                                m.prev_target.short(),
+                                                                m.target.short());
+
+                                           blox! {<context_block><text kind=mrkdwn>{merged}</text></context_block>}.into()
+                                         }));
+
+  blocks
+}
+
+/// Ask an approver to approve a job, linking to the request if we can
+fn approval_dm_msg(job: &Job<job::StateInit>, reminder: bool) -> Vec<slack_blocks::Block<'static>> {
+  use slack_blocks::blox::*;
+
+  let request = match &job.state.msg_id {
+    | Some(id) if !job.command.team_domain.is_empty() => {
+      format!("<{}|the request>", id.permalink(&job.command.team_domain))
+    },
+    | _ => format!("the request in <#{}>",
+                   job.app.notification_channel(&job.command.env_name)),
+  };
+
+  let body = match reminder {
+    | true => format!(":wave: Reminder: <@{}>'s deploy of {} to {} is still waiting on your approval. \
+                       React to {} with :+1: to approve.",
+                      job.command.user_id, job.app.name, job.command.env_name, request),
+    | false => format!("<@{}> requested a deploy of {} to {} and needs your approval. \
+                        React to {} with :+1: to approve.",
+                       job.command.user_id, job.app.name, job.command.env_name, request),
+  };
+
+  vec![blox! {<section_block><text kind=mrkdwn>{body}</text></section_block>}.into()]
+}
+
 impl<T: slack::msg::Messages> Messenger for T {
   fn send_job_created(&self, job: &Job<job::StateInit>) -> slack::Result<slack::msg::Id> {
     let blocks = job_created_msg(job);
 
-    self.send(&job.app.team_id, &job.app.notification_channel_id, &blocks)
+    self.send(&job.app.team_id,
+              job.app.notification_channel(&job.command.env_name),
+              &blocks)
         .map(|rep| rep.id)
   }
 
+  fn send_approval_dm(&self,
+                      job: &Job<job::StateInit>,
+                      user_id: &str,
+                      reminder: bool)
+                      -> slack::Result<slack::msg::Id> {
+    // posting to a user id sends the message to the user's DM with the bot
+    self.send(&job.app.team_id, user_id, &approval_dm_msg(job, reminder))
+        .map(|rep| rep.id)
+  }
+
+  fn send_approval_reminder(&self, job: &Job<job::StateInit>) -> slack::Result<slack::msg::Id> {
+    let id_missing = slack::Error::Other(String::from("no message to respond to"));
+    let id = job.state.msg_id.as_ref().ok_or(id_missing)?;
+
+    let waiting_on = job.outstanding_approvers()
+                        .into_iter()
+                        .filter(deploy::User::is_approver)
+                        .collect::<Vec<_>>();
+
+    let reminder = format!(":bell: This deploy is still waiting on {} to approve it with :+1:.",
+                           fmt_approvers(&waiting_on));
+
+    let blocks: Vec<slack_blocks::Block> = {
+      use slack_blocks::blox::*;
+      vec![blox! {<section_block><text kind=mrkdwn>{reminder}</text></section_block>}.into()]
+    };
+
+    self.send_thread(&job.app.team_id, id, &blocks).map(|rep| rep.id)
+  }
+
+  fn send_job_approved_by(&self, job: &Job<job::StateInit>, user: &deploy::User) -> slack::Result<slack::msg::Id> {
+    let id_missing = slack::Error::Other(String::from("no message to respond to"));
+    let id = job.state.msg_id.as_ref().ok_or(id_missing)?;
+
+    let approved = match user {
+      | deploy::User::User { user_id, .. } => format!("<@{}> approved :+1:", user_id),
+      | deploy::User::Group { group_id, .. } => format!("A member of <!subteam^{}> approved :+1:", group_id),
+    };
+
+    let waiting_on = job.outstanding_approvers()
+                        .into_iter()
+                        .filter(deploy::User::is_approver)
+                        .collect::<Vec<_>>();
+
+    let remaining = match waiting_on.is_empty() {
+      | true => String::from("That's everyone!"),
+      | false => format!("Still waiting on {}.", fmt_approvers(&waiting_on)),
+    };
+
+    let blocks: Vec<slack_blocks::Block> = {
+      use slack_blocks::blox::*;
+      vec![blox! {
+             <section_block>
+               <text kind=mrkdwn>{format!("{} {}", approved, remaining)}</text>
+             </section_block>
+           }.into()]
+    };
+
+    self.send_thread(&job.app.team_id, id, &blocks).map(|rep| rep.id)
+  }
+
   /// Notify that the job has been executed
   fn send_job_approved(&self, job: &Job<job::StateApproved>) -> slack::Result<slack::msg::Id> {
     let id_missing = slack::Error::Other(String::from("no message to respond to"));
@@ -144,12 +380,79 @@ impl<T: slack::msg::Messages> Messenger for T {
                 .as_ref()
                 .ok_or(id_missing)?;
 
+    let blocks = job_failed_msg(job);
+
+    self.send_thread(&job.app.team_id, id, &blocks).map(|rep| rep.id)
+  }
+
+  fn send_job_done(&self, job: &job::Job<job::StateDone>) -> slack::Result<slack::msg::Id> {
+    let id_missing = slack::Error::Other(String::from("no message to respond to"));
+    let id = match &job.state {
+               | job::StateDone::Succeeded(ref app, _) => &app.prev.msg_id,
+               | job::StateDone::SucceededAfterRetry(ref app, _) => &app.prev.prev.msg_id,
+             }.as_ref()
+              .ok_or(id_missing)?;
+
+    let changes = changelog(job, true);
+    let attach = changes.len() > MAX_CHANGELOG_LEN;
+
+    let done = match changes.is_empty() {
+      | true => String::from("Deploy merge succeeded! :rocket:"),
+      | false if attach => String::from("Deploy merge succeeded! :rocket: Here's what changed:"),
+      | false => format!("Deploy merge succeeded! :rocket: Here's what changed:\n\n{}", changes),
+    };
+
+    let blocks: Vec<slack_blocks::Block> = {
+      use slack_blocks::blox::*;
+      vec![blox! {<section_block><text kind=mrkdwn>{done}</text></section_block>}.into()]
+    };
+
+    let sent = self.send_thread(&job.app.team_id, id, &blocks)?;
+
+    // the done message was sent, so don't fail (and have it sent again) just because the changelog wasn't
+    if attach {
+      if let Err(e) = self.upload_snippet(&job.app.team_id, id, "Changelog", &changelog(job, false)) {
+        log::error!("job {:?}: failed to upload changelog {:?}", job.id, e);
+      }
+    }
+
+    Ok(sent.id)
+  }
+
+  fn send_job_announcement(&self, job: &Job<job::StateDone>, channel_id: &str) -> slack::Result<slack::msg::Id> {
+    self.send(&job.app.team_id, channel_id, &job_announcement_msg(job))
+        .map(|rep| rep.id)
+  }
+
+  fn send_job_scheduled(&self, job: &Job<job::StateScheduled>) -> slack::Result<slack::msg::Id> {
+    let id_missing = slack::Error::Other(String::from("no message to respond to"));
+    let id = job.state
+                .prev // approved
+                .prev // init
+                .msg_id
+                .as_ref()
+                .ok_or(id_missing)?;
+
+    let held = job.state
+                  .blocked
+                  .as_ref()
+                  .map(|blocked| format!("{}, so ", blocked))
+                  .unwrap_or_default();
+
+    let when = match job.state.held_by_lock() {
+      | true => String::from("once it's unlocked"),
+      | false => fmt_time(&job.state.at),
+    };
+
     let blocks: Vec<slack_blocks::Block> = {
       use slack_blocks::blox::*;
       vec![blox! {
              <section_block>
                <text kind=mrkdwn>
-                 {"Merge failed :skull_and_crossbones:"}
+                 {format!("{}I'll deploy to {} {} :alarm_clock: React to the request with :x: to cancel before then.",
+                          held,
+                          job.command.env_name,
+                          when)}
                </text>
              </section_block>
            }.into()]
@@ -158,20 +461,22 @@ impl<T: slack::msg::Messages> Messenger for T {
     self.send_thread(&job.app.team_id, id, &blocks).map(|rep| rep.id)
   }
 
-  fn send_job_done(&self, job: &job::Job<job::StateDone>) -> slack::Result<slack::msg::Id> {
+  fn send_job_cancelled(&self, job: &Job<job::StateCancelled>) -> slack::Result<slack::msg::Id> {
     let id_missing = slack::Error::Other(String::from("no message to respond to"));
-    let id = match &job.state {
-               | job::StateDone::Succeeded(ref app) => &app.prev.msg_id,
-               | job::StateDone::SucceededAfterRetry(ref app) => &app.prev.prev.msg_id,
-             }.as_ref()
-              .ok_or(id_missing)?;
+    let id = job.state
+                .prev // scheduled
+                .prev // approved
+                .prev // init
+                .msg_id
+                .as_ref()
+                .ok_or(id_missing)?;
 
     let blocks: Vec<slack_blocks::Block> = {
       use slack_blocks::blox::*;
       vec![blox! {
              <section_block>
                <text kind=mrkdwn>
-                 {"Deploy merge succeeded! :rocket:"}
+                 {format!("<@{}> cancelled this deploy :no_entry_sign:", job.state.by)}
                </text>
              </section_block>
            }.into()]
@@ -179,79 +484,102 @@ impl<T: slack::msg::Messages> Messenger for T {
 
     self.send_thread(&job.app.team_id, id, &blocks).map(|rep| rep.id)
   }
+
+  fn update_job_status(&self, job: &Job<job::States>) -> slack::Result<slack::msg::Id> {
+    let id_missing = slack::Error::Other(String::from("no message to update"));
+    let id = job.state.msg_id().ok_or(id_missing)?;
+
+    self.update(&job.app.team_id, id, &job_status_msg(job))
+        .map(|rep| rep.id)
+  }
+}
+
+/// Sends each job's messages through the chat service its app is configured with
+#[derive(Debug)]
+pub struct ChatMessenger {
+  /// Messenger for slack apps
+  pub slack: Box<dyn Messenger>,
+  /// Messenger for mattermost apps, if mattermost is configured
+  pub mattermost: Option<Box<dyn Messenger>>,
+}
+
+impl ChatMessenger {
+  fn to(&self, app: &deploy::App) -> slack::Result<&dyn Messenger> {
+    match app.chat {
+      | deploy::Chat::Slack => Ok(self.slack.as_ref()),
+      | deploy::Chat::Mattermost => {
+        self.mattermost
+            .as_deref()
+            .ok_or_else(|| slack::Error::Other(format!("{} uses mattermost, which isn't configured", app.name)))
+      },
+    }
+  }
+}
+
+impl Messenger for ChatMessenger {
+  fn send_job_created(&self, job: &Job<job::StateInit>) -> slack::Result<slack::msg::Id> {
+    self.to(&job.app)?.send_job_created(job)
+  }
+
+  fn send_approval_dm(&self,
+                      job: &Job<job::StateInit>,
+                      user_id: &str,
+                      reminder: bool)
+                      -> slack::Result<slack::msg::Id> {
+    self.to(&job.app)?.send_approval_dm(job, user_id, reminder)
+  }
+
+  fn send_approval_reminder(&self, job: &Job<job::StateInit>) -> slack::Result<slack::msg::Id> {
+    self.to(&job.app)?.send_approval_reminder(job)
+  }
+
+  fn send_job_approved_by(&self, job: &Job<job::StateInit>, user: &deploy::User) -> slack::Result<slack::msg::Id> {
+    self.to(&job.app)?.send_job_approved_by(job, user)
+  }
+
+  fn send_job_approved(&self, job: &Job<job::StateApproved>) -> slack::Result<slack::msg::Id> {
+    self.to(&job.app)?.send_job_approved(job)
+  }
+
+  fn send_job_failed(&self, job: &Job<job::StatePoisoned>) -> slack::Result<slack::msg::Id> {
+    self.to(&job.app)?.send_job_failed(job)
+  }
+
+  fn send_job_done(&self, job: &Job<job::StateDone>) -> slack::Result<slack::msg::Id> {
+    self.to(&job.app)?.send_job_done(job)
+  }
+
+  fn send_job_announcement(&self, job: &Job<job::StateDone>, channel_id: &str) -> slack::Result<slack::msg::Id> {
+    self.to(&job.app)?.send_job_announcement(job, channel_id)
+  }
+
+  fn send_job_scheduled(&self, job: &Job<job::StateScheduled>) -> slack::Result<slack::msg::Id> {
+    self.to(&job.app)?.send_job_scheduled(job)
+  }
+
+  fn send_job_cancelled(&self, job: &Job<job::StateCancelled>) -> slack::Result<slack::msg::Id> {
+    self.to(&job.app)?.send_job_cancelled(job)
+  }
+
+  fn update_job_status(&self, job: &Job<job::States>) -> slack::Result<slack::msg::Id> {
+    self.to(&job.app)?.update_job_status(job)
+  }
 }
 
 #[cfg(test)]
 mod tests {
+  use serde_json::json;
+
   use super::*;
+  use crate::job::fixture;
 
   #[test]
   fn test_job_created_msg() {
-    // json here is much more concise than struct initializers
-    let job = serde_json::json!({
-      "id": job::Id::new(),
-      "state": {
-        "msg_id": null,
-        "approved_by": []
-      },
-      "command": {
-        "app_name": "my_app",
-        "env_name": "prod",
-        "user_id": "U123",
-        "team_id": "T123"
-      },
-      "app": {
-        "name": "my_app",
-        "team_id": "T123",
-        "notification_channel_id": "C123",
-        "repos": [
-          {
-            "url": "git@foo.com:my/repo",
-            "human_url": "foo.com/my/repo",
-            "name": "ui",
-            "environments": [
-              {
-                "name": "prod",
-                "base": "staging",
-                "target": "prod",
-                "users": [
-                  {
-                    "user_id": "U123",
-                    "approver": true
-                  }
-                ]
-              }
-            ]
-          },
-          {
-            "url": "git@foo.com:my/repo2",
-            "human_url": "foo.com/my/repo2",
-            "name": "backend",
-            "environments": [
-              {
-                "name": "prod",
-                "base": "staging",
-                "target": "prod",
-                "users": [
-                  {
-                    "user_id": "U123",
-                    "approver": true
-                  },
-                  {
-                    "group_id": "G123",
-                    "min_approvers": 2
-                  }
-                ]
-              }
-            ]
-          }
-        ]
-      }
-    });
-
-    let job = serde_json::from_value::<Job<job::StateInit>>(job).unwrap();
-
-    println!("{}", serde_json::to_string_pretty(&job).unwrap());
+    let job = fixture::job::<job::StateInit>(json!({"msg_id": null, "approved_by": []}),
+                                             vec![fixture::repo("ui", json!([{"user_id": "U123", "approver": true}])),
+                                                  fixture::repo("backend",
+                                                                json!([{"user_id": "U123", "approver": true},
+                                                                       {"group_id": "G123", "min_approvers": 2}]))]);
 
     let msg = job_created_msg(&job);
 
@@ -260,8 +588,8 @@ mod tests {
 
       vec![
         blox!{<section_block><text kind=mrkdwn>{"<!here> <@U123> has requested a deploy merge for my_app to prod."}</text></section_block>}.into(),
-        blox!{<context_block><text kind=mrkdwn>{"ui changes: foo.com/my/repo/compare/prod..staging"}</text></context_block>}.into(),
-        blox!{<context_block><text kind=mrkdwn>{"backend changes: foo.com/my/repo2/compare/prod..staging"}</text></context_block>}.into(),
+        blox!{<context_block><text kind=mrkdwn>{"ui changes: foo.com/my/ui/compare/prod..staging"}</text></context_block>}.into(),
+        blox!{<context_block><text kind=mrkdwn>{"backend changes: foo.com/my/backend/compare/prod..staging"}</text></context_block>}.into(),
         blox!{<section_block><text kind=mrkdwn>{"In order to merge ui, I need <@U123> to react to this message with :+1:."}</text></section_block>}.into(),
         blox!{<section_block><text kind=mrkdwn>{"For backend, I need <@U123> & 2 members of <!subteam^G123> to approve."}</text></section_block>}.into(),
       ]
@@ -269,4 +597,68 @@ mod tests {
 
     assert_eq!(msg, expected)
   }
+
+  #[test]
+  fn test_job_status_msg() {
+    let state = json!({
+      "type": "init",
+      "msg_id": {"channel": "C123", "ts": "1603123456.000200"},
+      "approved_by": [{"user_id": "U456", "approver": true}]
+    });
+    let users = json!([{"user_id": "U456", "approver": true}, {"user_id": "U789", "approver": true}]);
+    let job = fixture::job::<job::States>(state, vec![fixture::repo("ui", users)]);
+
+    let msg = job_status_msg(&job);
+
+    let expected_status: Vec<slack_blocks::Block> = {
+      use slack_blocks::blox::*;
+
+      vec![
+        blox!{<section_block><text kind=mrkdwn>{"*Status:* waiting for approval from <@U789>"}</text></section_block>}.into(),
+        blox!{<section_block><text kind=mrkdwn>{"*Approved by:* <@U456>"}</text></section_block>}.into(),
+      ]
+    };
+
+    assert_eq!(msg[..3], job_created_msg(&job.map_state(|s| s.init().clone()))[..]);
+    assert_eq!(msg[3..], expected_status[..]);
+  }
+
+  #[test]
+  fn test_job_failed_msg() {
+    let init = json!({"msg_id": null, "approved_by": []});
+    let first = json!({
+      "prev": {"prev": init},
+      "prev_attempt": null,
+      "next_attempt": "2021-10-20T12:00:10Z",
+      "at": "2021-10-20T12:00:00Z",
+      "errs": [{"Git": ["ui", {"CouldNotSpawnGit": "not found"}]}]
+    });
+
+    let state = json!({
+      "prev": {
+        "prev": {"prev": init},
+        "prev_attempt": first,
+        "next_attempt": "2021-10-20T12:01:10Z",
+        "at": "2021-10-20T12:01:00Z",
+        "errs": [{"Git": ["ui", {"CommandFailed": ["git push", "rejected"]}]}]
+      }
+    });
+    let job = fixture::job::<job::StatePoisoned>(state, vec![]);
+
+    let msg = job_failed_msg(&job);
+
+    let expected: Vec<slack_blocks::Block> = {
+      use slack_blocks::blox::*;
+
+      vec![
+        blox!{<section_block><text kind=mrkdwn>{"Merge failed after 2 attempts :skull_and_crossbones:"}</text></section_block>}.into(),
+        blox!{<section_block><text kind=mrkdwn>{"*Attempt 1* failed <!date^1634731200^{date_short_pretty} at {time}|2021-10-20 12:00 UTC>:"}</text></section_block>}.into(),
+        blox!{<section_block><text kind=mrkdwn>{"ui: couldn't run git: not found"}</text></section_block>}.into(),
+        blox!{<section_block><text kind=mrkdwn>{"*Attempt 2* failed <!date^1634731260^{date_short_pretty} at {time}|2021-10-20 12:01 UTC>:"}</text></section_block>}.into(),
+        blox!{<section_block><text kind=mrkdwn>{"ui: `git push` failed\n```rejected```"}</text></section_block>}.into(),
+      ]
+    };
+
+    assert_eq!(msg, expected);
+  }
 }
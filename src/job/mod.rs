@@ -5,21 +5,71 @@ pub use messaging::*;
 
 pub mod event;
 pub mod exec;
+#[cfg(test)]
+pub(crate) mod fixture;
 pub mod hooks;
+pub mod reminders;
+pub mod status;
 pub mod store;
+pub mod webhooks;
 
 use chrono::{DateTime, Utc};
 pub use store::Store;
 
-use crate::{deploy::{App, Command, User},
+use crate::{deploy,
+            deploy::{App, Command, User},
             git,
             slack};
 
 /// Errors that a job can encounter trying to deploy
 #[derive(Debug, Clone, Ser, De)]
 pub enum Error {
-  /// Issue managing app repos
-  Git(git::Error),
+  /// Issue managing an app repo (repo name, error)
+  Git(String, git::Error),
+  /// A pre- or post-merge hook command failed in a repo (repo name, stage, error)
+  Hook(String, exec::hook::Stage, exec::hook::Error),
+  /// Environment couldn't be deployed when the job was executed
+  Blocked(deploy::Blocked),
+  /// A repo's commit hasn't been deployed to the previous stage of the app's promotion chain
+  /// (repo name, previous stage, commit)
+  NotPromoted(String, String, git::Sha),
+}
+
+impl std::fmt::Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    use exec::hook::Error as Hook;
+
+    /// Only the tail of command output is interesting, and slack caps message length
+    fn tail(out: &git::Output) -> String {
+      let out = out.as_ref().trim();
+      let start = out.char_indices().rev().nth(499).map(|(ix, _)| ix).unwrap_or(0);
+
+      match &out[start..] {
+        | "" => String::new(),
+        | tail => format!("\n```{}```", tail),
+      }
+    }
+
+    match self {
+      | Self::Git(repo, git::Error::CouldNotSpawnGit(e)) => write!(f, "{}: couldn't run git: {}", repo, e),
+      | Self::Git(repo, git::Error::CommandFailed(cmd, out)) => write!(f, "{}: `{}` failed{}", repo, cmd, tail(out)),
+      | Self::Git(repo, git::Error::NoBranchToUpdate) => write!(f, "{}: git error: no branch to update", repo),
+      | Self::Git(repo, git::Error::Other(e)) => write!(f, "{}: git error: {}", repo, e),
+      | Self::Hook(repo, stage, Hook::CouldNotSpawn(cmd, e)) => {
+        write!(f, "{}: couldn't run {} hook `{}`: {}", repo, stage, cmd, e)
+      },
+      | Self::Hook(repo, stage, Hook::TimedOut(cmd, out)) => {
+        write!(f, "{}: {} hook `{}` timed out{}", repo, stage, cmd, tail(out))
+      },
+      | Self::Hook(repo, stage, Hook::Failed(cmd, out)) => {
+        write!(f, "{}: {} hook `{}` failed{}", repo, stage, cmd, tail(out))
+      },
+      | Self::Blocked(blocked) => write!(f, "{}", blocked),
+      | Self::NotPromoted(repo, prev, sha) => {
+        write!(f, "{}: {} hasn't been deployed to {} yet", repo, sha.short(), prev)
+      },
+    }
+  }
 }
 
 /// Job ID
@@ -76,6 +126,16 @@ impl State for StateDone {
     States::Done(self)
   }
 }
+impl State for StateScheduled {
+  fn into_states(self) -> States {
+    States::Scheduled(self)
+  }
+}
+impl State for StateCancelled {
+  fn into_states(self) -> States {
+    States::Cancelled(self)
+  }
+}
 impl State for States {
   fn into_states(self) -> States {
     self
@@ -101,12 +161,50 @@ pub enum States {
   /// Done
   #[serde(rename = "done")]
   Done(StateDone),
+  /// Scheduled
+  #[serde(rename = "scheduled")]
+  Scheduled(StateScheduled),
+  /// Cancelled
+  #[serde(rename = "cancelled")]
+  Cancelled(StateCancelled),
 }
 
 impl States {
-  /// State is not Done or Poisoned
+  /// Name of the state, matching its serialized `type`
+  pub fn name(&self) -> &'static str {
+    match self {
+      | Self::Init(_) => "init",
+      | Self::Approved(_) => "approved",
+      | Self::Errored(_) => "errored",
+      | Self::Poisoned(_) => "poisoned",
+      | Self::Done(_) => "done",
+      | Self::Scheduled(_) => "scheduled",
+      | Self::Cancelled(_) => "cancelled",
+    }
+  }
+
+  /// State is not Done, Poisoned or Cancelled
   pub fn in_progress(&self) -> bool {
-    !matches!(self, Self::Done(_) | Self::Poisoned(_))
+    !matches!(self, Self::Done(_) | Self::Poisoned(_) | Self::Cancelled(_))
+  }
+
+  /// The state the job was in while it was waiting for approval
+  pub fn init(&self) -> &StateInit {
+    match self {
+      | Self::Init(s) => s,
+      | Self::Approved(s) => &s.prev,
+      | Self::Scheduled(s) => &s.prev.prev,
+      | Self::Cancelled(s) => &s.prev.prev.prev,
+      | Self::Errored(s) => &s.prev.prev,
+      | Self::Poisoned(s) => &s.prev.prev.prev,
+      | Self::Done(StateDone::Succeeded(s, _)) => &s.prev,
+      | Self::Done(StateDone::SucceededAfterRetry(s, _)) => &s.prev.prev,
+    }
+  }
+
+  /// ID of the slack notification for the job, if it was sent
+  pub fn msg_id(&self) -> Option<&slack::msg::Id> {
+    self.init().msg_id.as_ref()
   }
 }
 
@@ -127,6 +225,34 @@ pub struct StateApproved {
   pub prev: StateInit,
 }
 
+/// Job has been fully approved, and will be executed at a later time
+#[derive(Debug, Clone, Ser, De)]
+pub struct StateScheduled {
+  /// Previous state of the job
+  pub prev: StateApproved,
+  /// When the job will be executed
+  pub at: DateTime<Utc>,
+  /// Why the job was put off until `at`, if it was held back rather than scheduled by its requester
+  #[serde(default)]
+  pub blocked: Option<deploy::Blocked>,
+}
+
+impl StateScheduled {
+  /// Whether the job is waiting for its environment to be unlocked rather than for `at`
+  pub fn held_by_lock(&self) -> bool {
+    matches!(self.blocked, Some(deploy::Blocked::Locked(_)))
+  }
+}
+
+/// Job was cancelled before its scheduled time
+#[derive(Debug, Clone, Ser, De)]
+pub struct StateCancelled {
+  /// Previous state of the job
+  pub prev: StateScheduled,
+  /// ID of the user who cancelled the job
+  pub by: String,
+}
+
 /// Deploying this job failed. Will retry.
 #[derive(Debug, Clone, Ser, De)]
 pub struct StateErrored {
@@ -134,6 +260,9 @@ pub struct StateErrored {
   pub prev: StateApproved,
   /// Previous errored attempts
   pub prev_attempt: Option<Box<StateErrored>>,
+  /// When this attempt failed
+  #[serde(default = "Utc::now")]
+  pub at: DateTime<Utc>,
   /// Next scheduled attempt
   pub next_attempt: DateTime<Utc>,
   /// Errors encountered during last attempt
@@ -150,12 +279,44 @@ pub struct StatePoisoned {
 /// Job has been executed. Includes the previous approval state,
 /// and if deploy failed but eventually succeeded,
 /// includes error state that triggered retry.
+///
+/// Also includes what was merged in each repo.
 #[derive(Debug, Clone, Ser, De)]
 pub enum StateDone {
   /// Succeeded right away
-  Succeeded(StateApproved),
+  Succeeded(StateApproved, Vec<Merged>),
   /// Failed at least once, but eventually succeeded
-  SucceededAfterRetry(StateErrored),
+  SucceededAfterRetry(StateErrored, Vec<Merged>),
+}
+
+impl StateDone {
+  /// What was merged in each repo
+  pub fn merged(&self) -> &[Merged] {
+    match self {
+      | Self::Succeeded(_, merged) | Self::SucceededAfterRetry(_, merged) => merged,
+    }
+  }
+
+  /// When the last repo was merged
+  pub fn finished_at(&self) -> Option<DateTime<Utc>> {
+    self.merged().iter().map(|m| m.at).max()
+  }
+}
+
+/// A repo that was successfully merged & pushed
+#[derive(Debug, Clone, PartialEq, Ser, De)]
+pub struct Merged {
+  /// Name of the repo
+  pub repo: String,
+  /// Commit `target` pointed to before merging
+  pub prev_target: git::Sha,
+  /// Commit `target` points to after merging
+  pub target: git::Sha,
+  /// When the merge was pushed
+  pub at: DateTime<Utc>,
+  /// Commits the merge brought to `target`, newest first
+  #[serde(default)]
+  pub commits: Vec<git::Commit>,
 }
 
 /// A deploy job
@@ -169,6 +330,9 @@ pub struct Job<S: State> {
   pub command: Command,
   /// Application to deploy
   pub app: App,
+  /// When the job was created
+  #[serde(default = "Utc::now")]
+  pub created_at: DateTime<Utc>,
 }
 
 impl<T: State> Job<T> {
@@ -176,7 +340,8 @@ impl<T: State> Job<T> {
     Job { id: self.id.clone(),
           state: f(self.state.clone()),
           app: self.app.clone(),
-          command: self.command.clone() }
+          command: self.command.clone(),
+          created_at: self.created_at }
   }
 }
 
@@ -211,6 +376,6 @@ impl Job<StateErrored> {
       }
     }
 
-    go(&Some(Box::from(self.state.clone())), vec![self.state.clone()])
+    go(&Some(Box::from(self.state.clone())), vec![])
   }
 }
@@ -0,0 +1,114 @@
+use std::{collections::{HashMap, HashSet},
+          time::Duration};
+
+use chrono::{DateTime, Utc};
+
+use super::*;
+
+/// How often the scheduler checks for jobs that are due a reminder
+const TICK: Duration = Duration::from_secs(30);
+
+/// Whether a job waiting for approval is due a reminder,
+/// given when approvers were last reminded about it (if ever)
+fn due(job: &Job<StateInit>, last_reminded: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+  let interval = job.app
+                    .reminder_interval(&job.command.env_name)
+                    .and_then(|interval| chrono::Duration::from_std(interval).ok());
+
+  match interval {
+    | Some(interval) => now >= last_reminded.unwrap_or(job.created_at) + interval,
+    | None => false,
+  }
+}
+
+/// Whether a job waiting for approval is due its approvers being DMed again (see `App.dm_reminder_mins`)
+fn dm_due(job: &Job<StateInit>, now: DateTime<Utc>) -> bool {
+  match job.app.dm_reminder_mins {
+    | Some(mins) => now >= job.created_at + chrono::Duration::minutes(mins as i64),
+    | None => false,
+  }
+}
+
+/// Start a thread that reminds outstanding approvers of jobs waiting for approval,
+/// at the interval configured for the job's environment, until the job leaves `Init`.
+///
+/// Approvers are also DMed once more after the app's `dm_reminder_mins`.
+pub fn start(state: &'static crate::State) {
+  std::thread::spawn(move || {
+    let mut last_reminded: HashMap<Id, DateTime<Utc>> = HashMap::new();
+    let mut dm_reminded: HashSet<Id> = HashSet::new();
+
+    loop {
+      std::thread::sleep(TICK);
+
+      let now = Utc::now();
+      let waiting = state.jobs.get_all_new();
+
+      // forget jobs that have been approved (or otherwise left Init)
+      last_reminded.retain(|id, _| waiting.iter().any(|j| &j.id == id));
+      dm_reminded.retain(|id| waiting.iter().any(|j| &j.id == id));
+
+      // jobs whose request hasn't been sent can't be replied to,
+      // and fully approved jobs are about to leave Init
+      let remindable = waiting.iter()
+                              .filter(|j| j.state.msg_id.is_some() && !j.outstanding_approvers().is_empty());
+
+      for job in remindable {
+        if !dm_reminded.contains(&job.id) && dm_due(job, now) {
+          hooks::dm_approvers(state, job, true);
+          dm_reminded.insert(job.id.clone());
+        }
+
+        if !due(job, last_reminded.get(&job.id).copied(), now) {
+          continue;
+        }
+
+        log::info!("job {:?}: reminding outstanding approvers", job.id);
+        let reminded = job.clone();
+
+        hooks::notify(format!("approval reminder for job {:?}", job.id), move || {
+          state.job_messenger.send_approval_reminder(&reminded)
+        });
+
+        // the outbox retries rate limited reminders, don't retry failures every tick too
+        last_reminded.insert(job.id.clone(), now);
+      }
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+  use serde_json::json;
+
+  use super::*;
+  use crate::job::fixture;
+
+  #[test]
+  fn reminders_due() {
+    let init = json!({"msg_id": null, "approved_by": []});
+    let mut job = fixture::job::<StateInit>(init,
+                                            vec![fixture::repo("ui", json!([{"user_id": "U456", "approver": true}]))]);
+    job.app.repos[0].environments[0].reminder_interval_mins = Some(60);
+    let at = |h, m| Utc.ymd(2021, 10, 20).and_hms(h, m, 0);
+
+    assert!(!due(&job, None, at(12, 59)));
+    assert!(due(&job, None, at(13, 0)));
+    assert!(!due(&job, Some(at(13, 0)), at(13, 30)));
+    assert!(due(&job, Some(at(13, 0)), at(14, 0)));
+  }
+
+  #[test]
+  fn dm_reminders_due() {
+    let mut job = fixture::job::<StateInit>(json!({"msg_id": null, "approved_by": []}), vec![]);
+    let at = |h, m| Utc.ymd(2021, 10, 20).and_hms(h, m, 0);
+
+    assert!(!dm_due(&job, at(20, 0)));
+
+    job.app.dm_reminder_mins = Some(30);
+
+    assert!(!dm_due(&job, at(12, 29)));
+    assert!(dm_due(&job, at(12, 30)));
+  }
+}
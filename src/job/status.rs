@@ -0,0 +1,79 @@
+use super::{messaging::{fmt_approvers, fmt_time},
+            *};
+
+/// How many jobs `/deploy list` shows
+pub const RECENT_JOBS: usize = 10;
+
+fn fmt_errs(errs: &[Error]) -> String {
+  errs.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n")
+}
+
+/// Summarize what state a job is in, in mrkdwn
+pub fn summarize(job: &Job<States>) -> String {
+  match &job.state {
+    | States::Init(init) => {
+      let waiting_on = job.map_state(|_| init.clone())
+                          .outstanding_approvers()
+                          .into_iter()
+                          .filter(User::is_approver)
+                          .collect::<Vec<_>>();
+
+      match waiting_on.is_empty() {
+        | true => String::from("waiting for approval"),
+        | false => format!("waiting for approval from {}", fmt_approvers(&waiting_on)),
+      }
+    },
+    | States::Approved(_) => String::from("approved, deploying"),
+    | States::Scheduled(s) => match &s.blocked {
+      | Some(blocked) if s.held_by_lock() => format!("approved, held until unlocked: {}", blocked),
+      | Some(blocked) => format!("approved, held until {}: {}", fmt_time(&s.at), blocked),
+      | None => format!("approved, scheduled for {}", fmt_time(&s.at)),
+    },
+    | States::Cancelled(s) => format!("cancelled by <@{}>", s.by),
+    | States::Errored(s) => format!("failed, retrying {}:\n{}", fmt_time(&s.next_attempt), fmt_errs(&s.errs)),
+    | States::Poisoned(s) => format!("failed:\n{}", fmt_errs(&s.prev.errs)),
+    | States::Done(s) => match s.finished_at() {
+      | Some(at) => format!("deployed {}", fmt_time(&at)),
+      | None => String::from("deployed"),
+    },
+  }
+}
+
+/// Describe a job in a line of mrkdwn, for `/deploy status` and `/deploy list`
+pub fn describe(job: &Job<States>, team_domain: &str) -> String {
+  let state = summarize(job);
+
+  let link = job.state
+                .msg_id()
+                .map(|id| format!(" (<{}|message>)", id.permalink(team_domain)))
+                .unwrap_or_default();
+
+  format!("• *{} {}* requested by <@{}>{}: {}",
+          job.app.name, job.command.env_name, job.command.user_id, link, state)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::job::fixture;
+
+  #[test]
+  fn describe_waiting_for_approval() {
+    let state = serde_json::json!({
+      "type": "init",
+      "msg_id": {"channel": "C123", "ts": "1603123456.000200"},
+      "approved_by": [{"user_id": "U456", "approver": true}]
+    });
+    let users = serde_json::json!([
+      {"user_id": "U123", "approver": false},
+      {"user_id": "U456", "approver": true},
+      {"group_id": "G123", "min_approvers": 1}
+    ]);
+    let job = fixture::job::<States>(state, vec![fixture::repo("ui", users)]);
+
+    assert_eq!(describe(&job, "example"),
+               "• *my_app prod* requested by <@U123> \
+                (<https://example.slack.com/archives/C123/p1603123456000200|message>): \
+                waiting for approval from 1 member of <!subteam^G123>");
+  }
+}
@@ -1,11 +1,14 @@
 use std::{collections::HashMap,
+          fs,
+          io,
+          path::PathBuf,
           sync::{Arc, Mutex, MutexGuard}};
 
 use event::{Event, Listener};
 use serde::{Deserialize as De, Serialize as Ser};
 
 use super::*;
-use crate::{deploy, mutex_extra::lock_discard_poison, slack};
+use crate::{deploy, extra, mutex_extra::lock_discard_poison, slack};
 
 lazy_static::lazy_static! {
   static ref LISTENERS: Mutex<Vec<Listener>> = Mutex::new(Vec::new());
@@ -19,6 +22,20 @@ pub struct StoreData {
   pub errored: HashMap<Id, Job<StateErrored>>,
   pub poison: HashMap<Id, Job<StatePoisoned>>,
   pub done: HashMap<Id, Job<StateDone>>,
+  #[serde(default)]
+  pub scheduled: HashMap<Id, Job<StateScheduled>>,
+  #[serde(default)]
+  pub cancelled: HashMap<Id, Job<StateCancelled>>,
+  #[serde(default)]
+  pub locks: Vec<deploy::Lock>,
+  #[serde(default)]
+  pub notify_prefs: Vec<deploy::NotifyPrefs>,
+  /// Slack tokens, when they're kept alongside jobs rather than in their own file
+  #[serde(default)]
+  pub tokens: Vec<slack::access::AccessRep>,
+  /// File the store is written to whenever it changes, if any
+  #[serde(skip)]
+  path: Option<PathBuf>,
 }
 
 impl Default for StoreData {
@@ -33,7 +50,48 @@ impl StoreData {
            approved: HashMap::new(),
            errored: HashMap::new(),
            poison: HashMap::new(),
-           done: HashMap::new() }
+           done: HashMap::new(),
+           scheduled: HashMap::new(),
+           cancelled: HashMap::new(),
+           locks: Vec::new(),
+           notify_prefs: Vec::new(),
+           tokens: Vec::new(),
+           path: None }
+  }
+
+  /// Load the store from the file at `path`, or start an empty one if there isn't one yet.
+  ///
+  /// The store is written back to `path` whenever it changes, so jobs, locks and preferences survive restarts.
+  pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+    let path = path.into();
+    let store = match fs::read_to_string(&path) {
+      | Ok(json) => serde_json::from_str(&json)?,
+      | Err(e) if e.kind() == io::ErrorKind::NotFound => Self::new(),
+      | Err(e) => return Err(e),
+    };
+
+    Ok(Self { path: Some(path),
+              ..store })
+  }
+
+  /// Whether the store is kept in a file, rather than only in memory
+  pub fn persisted(&self) -> bool {
+    self.path.is_some()
+  }
+
+  /// Write the store to its file, if it has one
+  fn write(&self) -> io::Result<()> {
+    match &self.path {
+      | Some(path) => extra::write_atomic(path, serde_json::to_string(self)?),
+      | None => Ok(()),
+    }
+  }
+
+  /// Write the store to its file, if it has one, logging if that fails
+  fn persist(&self) {
+    if let Err(e) = self.write() {
+      log::error!("couldn't write job store to {:?}: {:?}", self.path, e);
+    }
   }
 }
 
@@ -59,6 +117,7 @@ trait EmitEvent {
 
 impl EmitEvent for Arc<Mutex<StoreData>> {
   fn emit(&self, lock: MutexGuard<'_, StoreData>, ev: Event) {
+    lock.persist();
     drop(lock);
 
     LISTENERS.open().iter().for_each(|f| f(ev));
@@ -68,10 +127,14 @@ impl EmitEvent for Arc<Mutex<StoreData>> {
 impl super::Store for Arc<Mutex<StoreData>> {
   /// Add a slack message id to a job in Init state
   fn notified(&self, job_id: &Id, msg_id: slack::msg::Id) -> Option<Id> {
-    self.open().created.get_mut(job_id).map(|j| {
-                                         j.state.msg_id = Some(msg_id);
-                                         j.id.clone()
-                                       })
+    let mut store = self.open();
+    let id = store.created.get_mut(job_id).map(|j| {
+                                            j.state.msg_id = Some(msg_id);
+                                            j.id.clone()
+                                          });
+
+    store.persist();
+    id
   }
 
   /// Create a new job, returning the created job's id
@@ -80,7 +143,8 @@ impl super::Store for Arc<Mutex<StoreData>> {
                     state: StateInit { approved_by: vec![],
                                        msg_id: None },
                     command,
-                    app };
+                    app,
+                    created_at: chrono::Utc::now() };
 
     let mut store = self.open();
     store.created.insert(job.id.clone(), job.clone());
@@ -131,6 +195,16 @@ impl super::Store for Arc<Mutex<StoreData>> {
     self.open().done.get(job_id).cloned()
   }
 
+  /// Get a job of state Scheduled
+  fn get_scheduled(&self, job_id: &Id) -> Option<Job<StateScheduled>> {
+    self.open().scheduled.get(job_id).cloned()
+  }
+
+  /// Get a job of state Cancelled
+  fn get_cancelled(&self, job_id: &Id) -> Option<Job<StateCancelled>> {
+    self.open().cancelled.get(job_id).cloned()
+  }
+
   /// Mark a job as fully approved
   fn fully_approved(&self, job_id: &Id) -> Option<Id> {
     let mut state = self.open();
@@ -148,27 +222,96 @@ impl super::Store for Arc<Mutex<StoreData>> {
     }
   }
 
+  /// Mark a fully approved job as scheduled for its command's `scheduled_for`
+  fn state_scheduled(&self, job_id: &Id) -> Option<Id> {
+    let mut store = self.open();
+
+    let job = store.approved.remove(job_id).map(|j| {
+                                             let at = j.command.scheduled_for.unwrap_or_else(Utc::now);
+                                             j.map_state(|prev| StateScheduled { prev,
+                                                                                 at,
+                                                                                 blocked: None })
+                                           });
+
+    if let Some(j) = job {
+      store.scheduled.insert(job_id.clone(), j.clone());
+      self.emit(store, Event::Scheduled(&j));
+      Some(job_id.clone())
+    } else {
+      None
+    }
+  }
+
+  /// Put off a job that isn't allowed to deploy yet until `at`
+  fn postponed(&self, job_id: &Id, at: DateTime<Utc>, blocked: deploy::Blocked) -> Option<Id> {
+    let mut store = self.open();
+
+    // Jobs are blocked when they're executed, so they may come from any state the executor picks up
+    let job = store.approved
+                   .remove(job_id)
+                   .or_else(|| store.scheduled.remove(job_id).map(|j| j.map_state(|s| s.prev)))
+                   .or_else(|| store.errored.remove(job_id).map(|j| j.map_state(|s| s.prev)))
+                   .map(|j| {
+                     j.map_state(|prev| StateScheduled { prev,
+                                                         at,
+                                                         blocked: Some(blocked) })
+                   });
+
+    if let Some(j) = job {
+      store.scheduled.insert(job_id.clone(), j.clone());
+      self.emit(store, Event::Scheduled(&j));
+      Some(job_id.clone())
+    } else {
+      None
+    }
+  }
+
+  /// Mark a scheduled job as cancelled by a user
+  fn cancelled(&self, job_id: &Id, user_id: &str) -> Option<Id> {
+    let mut store = self.open();
+
+    let job = store.scheduled.remove(job_id).map(|j| {
+                                              j.map_state(|prev| StateCancelled { prev,
+                                                                                  by: user_id.to_string() })
+                                            });
+
+    if let Some(j) = job {
+      store.cancelled.insert(job_id.clone(), j.clone());
+      self.emit(store, Event::Cancelled(&j));
+      Some(job_id.clone())
+    } else {
+      None
+    }
+  }
+
   /// Mark a job as errored
   fn state_errored(&self, job_id: &Id, errs: Vec<Error>) -> Option<Id> {
     use chrono::Duration as Dur;
 
     let mut store = self.open();
-    let next_attempt = Utc::now() + Dur::seconds(10);
+    let at = Utc::now();
+    let next_attempt = at + Dur::seconds(10);
 
     // Jobs can be transitioned to "Errored" from "Approved" or a previous "Errored"
     let errored = store.errored.remove(job_id).map(|j| {
                                                 j.map_state(|e| StateErrored { prev: e.prev.clone(),
                                                                                prev_attempt: Some(Box::from(e)),
+                                                                               at,
                                                                                next_attempt,
                                                                                errs: errs.clone() })
                                               });
 
-    let approved = store.approved.remove(job_id).map(|j| {
-                                                  j.map_state(|a| StateErrored { prev: a,
-                                                                                 prev_attempt: None,
-                                                                                 next_attempt,
-                                                                                 errs })
-                                                });
+    // Scheduled jobs skip "Approved" when they're executed
+    let approved = store.approved
+                        .remove(job_id)
+                        .or_else(|| store.scheduled.remove(job_id).map(|j| j.map_state(|s| s.prev)))
+                        .map(|j| {
+                          j.map_state(|a| StateErrored { prev: a,
+                                                         prev_attempt: None,
+                                                         at,
+                                                         next_attempt,
+                                                         errs })
+                        });
 
     if let Some(j) = errored.or(approved) {
       store.errored.insert(job_id.clone(), j.clone());
@@ -196,12 +339,15 @@ impl super::Store for Arc<Mutex<StoreData>> {
   }
 
   /// Mark a job as done
-  fn state_done(&self, job_id: &Id) -> Option<Id> {
+  fn state_done(&self, job_id: &Id, merged: Vec<Merged>) -> Option<Id> {
     let mut store = self.open();
     let retried = store.errored
                        .remove(job_id)
-                       .map(|j| j.map_state(StateDone::SucceededAfterRetry));
-    let succeeded = store.approved.remove(job_id).map(|j| j.map_state(StateDone::Succeeded));
+                       .map(|j| j.map_state(|e| StateDone::SucceededAfterRetry(e, merged.clone())));
+    let succeeded = store.approved
+                         .remove(job_id)
+                         .or_else(|| store.scheduled.remove(job_id).map(|j| j.map_state(|s| s.prev)))
+                         .map(|j| j.map_state(|a| StateDone::Succeeded(a, merged)));
 
     if let Some(job) = succeeded.or(retried) {
       store.done.insert(job_id.clone(), job.clone());
@@ -212,6 +358,53 @@ impl super::Store for Arc<Mutex<StoreData>> {
     }
   }
 
+  /// Lock an environment, yielding the existing lock instead if it's already locked
+  fn lock(&self, lock: deploy::Lock) -> Option<deploy::Lock> {
+    let mut store = self.open();
+    let existing = store.locks
+                        .iter()
+                        .find(|l| l.matches(&lock.team_id, &lock.app_name, &lock.env_name))
+                        .cloned();
+
+    if existing.is_none() {
+      store.locks.push(lock);
+      store.persist();
+    }
+
+    existing
+  }
+
+  /// Unlock an environment, yielding the removed lock if it was locked
+  fn unlock(&self, team_id: &str, app_name: &str, env_name: &str) -> Option<deploy::Lock> {
+    let mut store = self.open();
+    let ix = store.locks.iter().position(|l| l.matches(team_id, app_name, env_name));
+
+    let removed = ix.map(|ix| store.locks.remove(ix));
+
+    store.persist();
+    removed
+  }
+
+  /// Get all locked environments
+  fn get_all_locks(&self) -> Vec<deploy::Lock> {
+    self.open().locks.clone()
+  }
+
+  /// Save a user's notification preferences, replacing any they had
+  fn set_notify_prefs(&self, prefs: deploy::NotifyPrefs) {
+    let mut store = self.open();
+
+    store.notify_prefs
+         .retain(|p| !p.matches(&prefs.team_id, &prefs.user_id));
+    store.notify_prefs.push(prefs);
+    store.persist();
+  }
+
+  /// Get every user's notification preferences
+  fn get_all_notify_prefs(&self) -> Vec<deploy::NotifyPrefs> {
+    self.open().notify_prefs.clone()
+  }
+
   /// Listen for events, allows mutating the store while processing with the provided &Self parameter
   fn attach_listener(&self, f: Listener) {
     LISTENERS.open().push(f)
@@ -241,4 +434,68 @@ impl super::Store for Arc<Mutex<StoreData>> {
   fn get_all_done(&self) -> Vec<Job<StateDone>> {
     self.open().done.values().cloned().collect()
   }
+
+  /// Get all scheduled jobs
+  fn get_all_scheduled(&self) -> Vec<Job<StateScheduled>> {
+    self.open().scheduled.values().cloned().collect()
+  }
+
+  /// Get all cancelled jobs
+  fn get_all_cancelled(&self) -> Vec<Job<StateCancelled>> {
+    self.open().cancelled.values().cloned().collect()
+  }
+}
+
+/// Keeps slack tokens alongside jobs, so they live in the same file
+impl slack::tokens::TokenMgr for Arc<Mutex<StoreData>> {
+  fn tokens(&self) -> slack::tokens::Result<Vec<slack::access::AccessRep>> {
+    Ok(self.open().tokens.clone())
+  }
+
+  fn set_tokens(&self, reps: Vec<slack::access::AccessRep>) -> slack::tokens::Result<()> {
+    let mut store = self.open();
+    let prev = std::mem::replace(&mut store.tokens, reps);
+
+    // only keep the new tokens if they've been written
+    store.write().map_err(|e| {
+                   store.tokens = prev;
+                   slack::tokens::Error::Io(e)
+                 })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::{job::{fixture, Store},
+              slack::{access::{AccessRep, Team},
+                      tokens::TokenMgr}};
+
+  #[test]
+  fn survives_reopening() {
+    let path = std::env::temp_dir().join(format!("mergebot-jobs-{}.json", nanoid::nanoid!()));
+    let store = Arc::new(Mutex::new(StoreData::open(&path).unwrap()));
+
+    store.set_notify_prefs(deploy::NotifyPrefs { team_id: "T123".into(),
+                                                 user_id: "U123".into(),
+                                                 dm: true });
+    let job: Job<StateInit> = fixture::job(json!({ "approved_by": [] }), vec![]);
+    let id = store.create(job.app, job.command);
+    store.register(&AccessRep { access_token: "xoxb-1".into(),
+                                scope: String::new(),
+                                bot_user_id: String::new(),
+                                team: Team { id: "T123".into(),
+                                             name: String::new() } })
+         .unwrap();
+
+    let reopened = Arc::new(Mutex::new(StoreData::open(&path).unwrap()));
+
+    assert_eq!(reopened.get_all_notify_prefs(), store.get_all_notify_prefs());
+    assert!(reopened.get_new(&id).is_some());
+    assert_eq!(reopened.tokens().unwrap(), store.tokens().unwrap());
+
+    fs::remove_file(&path).unwrap();
+  }
 }
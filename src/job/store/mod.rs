@@ -23,6 +23,12 @@ pub trait Store: 'static + Send + Sync + std::fmt::Debug {
   /// Get all complete jobs
   fn get_all_done(&self) -> Vec<Job<StateDone>>;
 
+  /// Get all scheduled jobs
+  fn get_all_scheduled(&self) -> Vec<Job<StateScheduled>>;
+
+  /// Get all cancelled jobs
+  fn get_all_cancelled(&self) -> Vec<Job<StateCancelled>>;
+
   /// Get all jobs
   fn get_all(&self) -> Vec<Job<States>> {
     fn norm<S: State>(v: Vec<Job<S>>) -> impl Iterator<Item = Job<States>> {
@@ -33,6 +39,8 @@ pub trait Store: 'static + Send + Sync + std::fmt::Debug {
                             .chain(norm(self.get_all_errored()))
                             .chain(norm(self.get_all_poisoned()))
                             .chain(norm(self.get_all_done()))
+                            .chain(norm(self.get_all_scheduled()))
+                            .chain(norm(self.get_all_cancelled()))
                             .collect::<Vec<_>>()
   }
 
@@ -60,23 +68,40 @@ pub trait Store: 'static + Send + Sync + std::fmt::Debug {
   /// Get a job of state Done
   fn get_done(&self, job_id: &Id) -> Option<Job<StateDone>>;
 
+  /// Get a job of state Scheduled
+  fn get_scheduled(&self, job_id: &Id) -> Option<Job<StateScheduled>>;
+
+  /// Get a job of state Cancelled
+  fn get_cancelled(&self, job_id: &Id) -> Option<Job<StateCancelled>>;
+
   /// Get a job of any state, converting its state from a concrete type to a polymorphic one.
   fn get(&self, job_id: &Id) -> Option<Job<States>> {
     fn norm<S: State>(j: Job<S>) -> Job<States> {
       j.map_state(|s| s.into_states())
     }
 
-    self.get_new(&job_id)
+    self.get_new(job_id)
         .map(norm)
-        .or_else(|| self.get_approved(&job_id).map(norm))
-        .or_else(|| self.get_errored(&job_id).map(norm))
-        .or_else(|| self.get_poisoned(&job_id).map(norm))
-        .or_else(|| self.get_done(&job_id).map(norm))
+        .or_else(|| self.get_approved(job_id).map(norm))
+        .or_else(|| self.get_errored(job_id).map(norm))
+        .or_else(|| self.get_poisoned(job_id).map(norm))
+        .or_else(|| self.get_done(job_id).map(norm))
+        .or_else(|| self.get_scheduled(job_id).map(norm))
+        .or_else(|| self.get_cancelled(job_id).map(norm))
   }
 
   /// Mark a job as fully approved
   fn fully_approved(&self, job_id: &Id) -> Option<Id>;
 
+  /// Mark a fully approved job as scheduled for its command's `scheduled_for`
+  fn state_scheduled(&self, job_id: &Id) -> Option<Id>;
+
+  /// Put off a job that isn't allowed to deploy yet until `at`
+  fn postponed(&self, job_id: &Id, at: DateTime<Utc>, blocked: deploy::Blocked) -> Option<Id>;
+
+  /// Mark a scheduled job as cancelled by a user
+  fn cancelled(&self, job_id: &Id, user_id: &str) -> Option<Id>;
+
   /// Mark a job as errored
   fn state_errored(&self, job_id: &Id, errs: Vec<Error>) -> Option<Id>;
 
@@ -84,7 +109,46 @@ pub trait Store: 'static + Send + Sync + std::fmt::Debug {
   fn state_poisoned(&self, job_id: &Id) -> Option<Id>;
 
   /// Mark a job as done
-  fn state_done(&self, job_id: &Id) -> Option<Id>;
+  fn state_done(&self, job_id: &Id, merged: Vec<Merged>) -> Option<Id>;
+
+  /// Get the most recently completed job for an app's environment
+  fn get_last_done(&self, team_id: &str, app_name: &str, env_name: &str) -> Option<Job<StateDone>> {
+    use crate::extra::StrExtra;
+
+    self.get_all_done()
+        .into_iter()
+        .filter(|j| j.app.team_id == team_id && j.app.name.loose_eq(app_name) && j.command.env_name.loose_eq(env_name))
+        .max_by_key(|j| j.state.finished_at())
+  }
+
+  /// Lock an environment, yielding the existing lock instead if it's already locked
+  fn lock(&self, lock: deploy::Lock) -> Option<deploy::Lock>;
+
+  /// Unlock an environment, yielding the removed lock if it was locked
+  fn unlock(&self, team_id: &str, app_name: &str, env_name: &str) -> Option<deploy::Lock>;
+
+  /// Get all locked environments
+  fn get_all_locks(&self) -> Vec<deploy::Lock>;
+
+  /// Get the lock on an app's environment, if it's locked
+  fn get_lock(&self, team_id: &str, app_name: &str, env_name: &str) -> Option<deploy::Lock> {
+    self.get_all_locks()
+        .into_iter()
+        .find(|lock| lock.matches(team_id, app_name, env_name))
+  }
+
+  /// Save a user's notification preferences, replacing any they had
+  fn set_notify_prefs(&self, prefs: deploy::NotifyPrefs);
+
+  /// Get every user's notification preferences
+  fn get_all_notify_prefs(&self) -> Vec<deploy::NotifyPrefs>;
+
+  /// Get a user's notification preferences, if they've set any
+  fn get_notify_prefs(&self, team_id: &str, user_id: &str) -> Option<deploy::NotifyPrefs> {
+    self.get_all_notify_prefs()
+        .into_iter()
+        .find(|prefs| prefs.matches(team_id, user_id))
+  }
 
   /// Listen for events, allows mutating the store while processing with the provided &Self parameter
   fn attach_listener(&self, f: event::Listener);
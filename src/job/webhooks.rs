@@ -0,0 +1,267 @@
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize as De, Serialize as Ser};
+
+use super::{event::*, *};
+use crate::{deploy, mutex_extra::lock_discard_poison};
+
+/// How many times to try delivering an event before giving up
+const MAX_ATTEMPTS: u32 = 3;
+
+/// How long to wait before retrying the first failed delivery, doubled for each retry after
+const BACKOFF: Duration = Duration::from_secs(2);
+
+/// How many deliveries the delivery log remembers
+const LOG_LEN: usize = 100;
+
+/// A repo in a webhook payload
+#[derive(Ser, De, PartialEq, Clone, Debug)]
+pub struct PayloadRepo {
+  /// Name of the repo
+  pub name: String,
+  /// Commit the environment's target branch pointed to before the deploy, once deployed
+  pub prev_sha: Option<String>,
+  /// Commit the environment's target branch points to after the deploy, once deployed
+  pub sha: Option<String>,
+}
+
+/// JSON body POSTed to an app's webhooks for each job event
+#[derive(Ser, De, PartialEq, Clone, Debug)]
+pub struct Payload {
+  /// What happened, e.g. `created`, `approved`, `done`
+  pub event: String,
+  /// ID of the job it happened to
+  pub job_id: String,
+  /// Name of the app being deployed
+  pub app: String,
+  /// Name of the environment being deployed
+  pub env: String,
+  /// State the job is now in, e.g. `init`, `errored`, `done`
+  pub state: String,
+  /// ID of the slack user who requested the deploy
+  pub requested_by: String,
+  /// IDs of the slack users (or groups) who've approved the deploy
+  pub approvers: Vec<String>,
+  /// The repos being deployed
+  pub repos: Vec<PayloadRepo>,
+  /// When the event happened
+  pub at: DateTime<Utc>,
+}
+
+impl Payload {
+  /// Describe a job event
+  pub fn new(ev: &Event<'_>, at: DateTime<Utc>) -> Self {
+    let job = ev.job();
+
+    let merged = match &job.state {
+      | States::Done(done) => done.merged().to_vec(),
+      | _ => vec![],
+    };
+
+    let repos = job.app
+                   .repos
+                   .iter()
+                   .map(|repo| {
+                     let merged = merged.iter().find(|m| m.repo == repo.name);
+
+                     PayloadRepo { name: repo.name.clone(),
+                                   prev_sha: merged.map(|m| m.prev_target.0.clone()),
+                                   sha: merged.map(|m| m.target.0.clone()) }
+                   })
+                   .collect();
+
+    let approvers = job.state
+                       .init()
+                       .approved_by
+                       .iter()
+                       .map(|user| match user {
+                         | deploy::User::User { user_id, .. } => user_id.clone(),
+                         | deploy::User::Group { group_id, .. } => group_id.clone(),
+                       })
+                       .collect();
+
+    Self { event: ev.name().to_string(),
+           job_id: job.id.to_string(),
+           app: job.app.name.clone(),
+           env: job.command.env_name.clone(),
+           state: job.state.name().to_string(),
+           requested_by: job.command.user_id.clone(),
+           approvers,
+           repos,
+           at }
+  }
+}
+
+/// An attempt to deliver an event to a webhook, kept in the delivery log
+#[derive(Ser, De, PartialEq, Clone, Debug)]
+pub struct Delivery {
+  /// URL the event was POSTed to
+  pub url: String,
+  /// What happened
+  pub event: String,
+  /// ID of the job it happened to
+  pub job_id: String,
+  /// How many times we tried
+  pub attempts: u32,
+  /// HTTP status of the last attempt, if the endpoint responded
+  pub status: Option<u16>,
+  /// Why the last attempt failed, if it did
+  pub error: Option<String>,
+  /// When the last attempt was made
+  pub at: DateTime<Utc>,
+}
+
+impl Delivery {
+  /// Whether the endpoint accepted the event
+  pub fn succeeded(&self) -> bool {
+    self.error.is_none()
+  }
+}
+
+/// Sign a payload so receivers can check it came from us:
+/// hex HMAC-SHA256 of `<timestamp>.<body>`, keyed with the webhook's secret
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+  use hmac::{Hmac, Mac, NewMac};
+  use sha2::Sha256;
+
+  let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take keys of any size");
+  mac.update(format!("{}.", timestamp).as_bytes());
+  mac.update(body);
+
+  hex::encode(mac.finalize().into_bytes())
+}
+
+/// POST a payload to a webhook, retrying with backoff if it fails
+pub fn deliver(client: &reqwest::blocking::Client, hook: &deploy::Webhook, payload: &Payload) -> Delivery {
+  let body = serde_json::to_vec(payload).expect("payload should serialize");
+  let mut attempts = 0;
+
+  loop {
+    attempts += 1;
+
+    let now = Utc::now();
+    let rep = client.post(&hook.url)
+                    .header("content-type", "application/json")
+                    .header("x-mergebot-timestamp", now.timestamp().to_string())
+                    .header("x-mergebot-signature",
+                            format!("sha256={}", sign(&hook.secret, now.timestamp(), &body)))
+                    .body(body.clone())
+                    .send();
+
+    let status = rep.as_ref().ok().map(|rep| rep.status().as_u16());
+    let error = match rep.and_then(|rep| rep.error_for_status()) {
+      | Ok(_) => None,
+      | Err(e) => Some(e.to_string()),
+    };
+
+    if error.is_none() || attempts >= MAX_ATTEMPTS {
+      return Delivery { url: hook.url.clone(),
+                        event: payload.event.clone(),
+                        job_id: payload.job_id.clone(),
+                        attempts,
+                        status,
+                        error,
+                        at: now };
+    }
+
+    std::thread::sleep(BACKOFF * 2u32.pow(attempts - 1));
+  }
+}
+
+/// Record of attempts to deliver events to webhooks
+pub trait Log: 'static + Sync + Send + std::fmt::Debug {
+  /// Add a delivery to the log
+  fn record(&self, delivery: Delivery);
+
+  /// Most recent deliveries, newest first
+  fn deliveries(&self) -> Vec<Delivery>;
+}
+
+/// Delivery log kept in memory, remembering the most recent `LOG_LEN` deliveries
+#[derive(Debug, Default)]
+pub struct MemLog(Mutex<VecDeque<Delivery>>);
+
+impl Log for MemLog {
+  fn record(&self, delivery: Delivery) {
+    let mut entries = lock_discard_poison(&self.0);
+
+    entries.push_back(delivery);
+
+    while entries.len() > LOG_LEN {
+      entries.pop_front();
+    }
+  }
+
+  fn deliveries(&self) -> Vec<Delivery> {
+    lock_discard_poison(&self.0).iter().rev().cloned().collect()
+  }
+}
+
+/// POST every job event to the job's app's webhooks
+pub fn on_event_deliver(state: &'static crate::State) -> Listener {
+  let f = move |ev: Event| {
+    let job = ev.job();
+
+    // jobs don't keep their app's webhooks (see `deploy::App::webhooks`), so find them in its current config
+    let hooks = match state.app_reader.get_app(&job.app.team_id, &job.app.name) {
+      | Ok(app) => app.webhooks,
+      | Err(e) => {
+        log::error!("job {:?}: couldn't read webhooks {:?}", job.id, e);
+        return;
+      },
+    };
+
+    if hooks.is_empty() {
+      return;
+    }
+
+    let payload = Payload::new(&ev, Utc::now());
+
+    // don't hold up other listeners while waiting on (or retrying) slow endpoints
+    for hook in hooks {
+      let payload = payload.clone();
+      std::thread::spawn(move || {
+        let delivery = deliver(state.reqwest_client, &hook, &payload);
+
+        if !delivery.succeeded() {
+          log::error!("couldn't deliver {:?} to webhook: {:?}", delivery.event, delivery);
+        }
+
+        state.webhook_log.record(delivery);
+      });
+    }
+  };
+
+  Box::from(f)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sign_payload() {
+    assert_eq!(sign("shh", 1634731200, br#"{"event":"created"}"#),
+               "2bec25b5ec529b56be67513331a41cc56049586d4d945e74eb185520a6afa7a2");
+  }
+
+  #[test]
+  fn mem_log_forgets_oldest() {
+    let log = MemLog::default();
+    let delivery = |n: usize| Delivery { url: "https://foo.com/hook".into(),
+                                         event: "created".into(),
+                                         job_id: n.to_string(),
+                                         attempts: 1,
+                                         status: Some(200),
+                                         error: None,
+                                         at: Utc::now() };
+
+    (0..LOG_LEN + 5).map(delivery).for_each(|d| log.record(d));
+
+    let deliveries = log.deliveries();
+    assert_eq!(deliveries.len(), LOG_LEN);
+    assert_eq!(deliveries.first().unwrap().job_id, (LOG_LEN + 4).to_string());
+    assert_eq!(deliveries.last().unwrap().job_id, "5");
+  }
+}
@@ -16,6 +16,9 @@ pub mod extra;
 /// Slack models
 pub mod slack;
 
+/// Mattermost models
+pub mod mattermost;
+
 /// Git stuff
 pub mod git;
 
@@ -25,6 +28,9 @@ pub mod deploy;
 /// Job queue stuff
 pub mod job;
 
+/// Job notifications outside of slack
+pub mod notify;
+
 // I chose to use dyn boxes rather than generics here for code footprint and code footprint alone.
 // If scale was a concern, I would want to change:
 //   `State {t: Box<dyn Trait>}`
@@ -39,10 +45,18 @@ pub struct State {
   pub slack_client_id: String,
   /// slack app client secret
   pub slack_client_secret: String,
+  /// token mattermost sends with `/deploy`, if mattermost is configured
+  pub mattermost_command_token: Option<String>,
+  /// secret mattermost sends back when our buttons are clicked, if mattermost is configured
+  pub mattermost_action_secret: Option<String>,
   /// API token used to access jobs api
   pub api_key: String,
   /// notifies approvers
   pub job_messenger: Box<dyn job::Messenger>,
+  /// tells the other places apps ask to be notified about their jobs
+  pub notifier: Box<dyn notify::Notifier>,
+  /// recent attempts to deliver job events to apps' webhooks
+  pub webhook_log: Box<dyn job::webhooks::Log>,
   /// Job queue
   pub jobs: Box<dyn job::Store>,
   /// Reader for deployable app configuration
@@ -53,8 +67,14 @@ pub struct State {
   pub slack_groups: Box<dyn slack::groups::Groups>,
   /// slack msg API
   pub slack_msg: Box<dyn slack::msg::Messages>,
+  /// slack slash command response API
+  pub slack_respond: Box<dyn slack::respond::Respond>,
   /// slack Oauth Access
   pub slack_access: Box<dyn slack::access::Access>,
+  /// slack bot tokens for each workspace we're installed in
+  pub slack_tokens: &'static dyn slack::tokens::TokenMgr,
+  /// install `state`s that have already been used
+  pub slack_installs: slack::install::Used,
   /// git client
   pub git: Box<dyn git::Client>,
   /// transition jobs from "Approved" -> "Done" | "Poisoned"
@@ -64,18 +84,99 @@ pub struct State {
 lazy_static::lazy_static! {
   pub static ref APP_INIT: Arc<Barrier> = Arc::new(Barrier::new(2));
   pub static ref CLIENT: reqwest::blocking::Client =reqwest::blocking::Client::new();
+  static ref JOBS: Arc<Mutex<job::store::StoreData>> = {
+    // kept in a file if asked so that jobs, locks and preferences outlive restarts
+    let jobs = match env::var("JOBS_PATH").ok().filter(|path| !path.is_empty()) {
+      | Some(path) => {
+        job::store::StoreData::open(&path).unwrap_or_else(|e| panic!("can't open job store {}: {:?}", path, e))
+      },
+      | None => job::store::StoreData::new(),
+    };
+
+    Arc::new(Mutex::new(jobs))
+  };
+  static ref SLACK_TOKENS: slack::tokens::Cached = {
+    // tokens live in the job store if asked, as long as it's kept in a file, otherwise in their own file
+    let store: Box<dyn slack::tokens::TokenMgr> = match env::var("SLACK_TOKEN_STORE").as_deref() {
+      | Ok("jobs") if !mutex_extra::lock_discard_poison(&JOBS).persisted() => {
+        panic!("JOBS_PATH required when SLACK_TOKEN_STORE=jobs")
+      },
+      | Ok("jobs") => Box::from(JOBS.clone()),
+      | _ => {
+        let path = env::var("SLACK_TOKENS_PATH").unwrap_or_else(|_| String::from("./access_reps.json"));
+        Box::from(slack::tokens::Fs::new(path))
+      },
+    };
+
+    // and are sealed before they get there if we have a key
+    let key = |b64: &str| {
+      slack::sealed::Key::from_base64(b64).expect("slack token keys must be 32 base64-encoded bytes")
+    };
+    let required = env::var("SLACK_TOKENS_ENCRYPTION").as_deref() == Ok("required");
+    let old_keys = env::var("SLACK_TOKENS_OLD_KEYS").unwrap_or_default()
+                                                    .split(',')
+                                                    .filter(|k| !k.trim().is_empty())
+                                                    .map(key)
+                                                    .collect();
+
+    let store: Box<dyn slack::tokens::TokenMgr> = match env::var("SLACK_TOKENS_KEY").ok().filter(|k| !k.is_empty()) {
+      | Some(k) => {
+        let sealed = slack::sealed::Sealed::open(store, key(k.as_str()), old_keys, required);
+        Box::from(sealed.unwrap_or_else(|e| panic!("can't open slack tokens: {:?}", e)))
+      },
+      | None if required => panic!("SLACK_TOKENS_KEY required when SLACK_TOKENS_ENCRYPTION=required"),
+      | None => {
+        slack::sealed::check_unsealed(&*store).unwrap_or_else(|e| panic!("can't open slack tokens: {:?}", e));
+        store
+      },
+    };
+
+    slack::tokens::Cached::new(store)
+  };
   pub static ref STATE: State = {
     // Environment
     let api_key = env::var("API_KEY").expect("API_KEY required");
+    let public_url = env::var("PUBLIC_URL").ok();
     let slack_signing_secret = env::var("SLACK_SIGNING_SECRET").expect("SLACK_SIGNING_SECRET required");
     let slack_client_id = env::var("SLACK_CLIENT_ID").expect("SLACK_CLIENT_ID required");
     let slack_client_secret = env::var("SLACK_CLIENT_SECRET").expect("SLACK_CLIENT_SECRET required");
 
     // Slack API
-    let slack_api = slack::Api::new("https://www.slack.com", &slack::tokens::Fs, &CLIENT);
-    let slack_groups = Box::from(slack_api.clone());
-    let job_messenger = Box::from(slack_api.clone());
+    let slack_tokens: &'static dyn slack::tokens::TokenMgr = &*SLACK_TOKENS;
+    let slack_api = slack::Api::new("https://www.slack.com", slack_tokens, &CLIENT);
+    let slack_groups_ttl = env::var("SLACK_GROUPS_TTL_SECS").ok()
+                                                            .and_then(|secs| secs.parse().ok())
+                                                            .unwrap_or(300);
+    let slack_groups = Box::from(slack::cache::CachedGroups::new(slack_api.clone(),
+                                                                 std::time::Duration::from_secs(slack_groups_ttl)));
+
+    // Mattermost API
+    let mattermost_url = env::var("MATTERMOST_URL").ok().filter(|url| !url.is_empty());
+    let mattermost_var = |name: &str| {
+      mattermost_url.as_ref()
+                    .map(|_| env::var(name).unwrap_or_else(|_| panic!("{} required when MATTERMOST_URL is set", name)))
+    };
+    let mattermost_command_token = mattermost_var("MATTERMOST_COMMAND_TOKEN");
+    let mattermost_action_secret = mattermost_var("MATTERMOST_ACTION_SECRET");
+    let mattermost_api = mattermost_url.as_ref().map(|url| {
+      let public_url = public_url.as_ref().expect("PUBLIC_URL required when MATTERMOST_URL is set");
+
+      mattermost::Api::new(url,
+                           mattermost_var("MATTERMOST_BOT_TOKEN").unwrap(),
+                           format!("{}/api/v1/mattermost/action", public_url.trim_end_matches('/')),
+                           mattermost_action_secret.clone().unwrap(),
+                           &CLIENT)
+    });
+
+    let mattermost_messenger = mattermost_api.map(|api| -> Box<dyn job::Messenger> { Box::from(api) });
+    let job_messenger = Box::from(job::ChatMessenger { slack: Box::from(slack_api.clone()),
+                                                       mattermost: mattermost_messenger });
+    let notifier = Box::from(notify::Backends { slack: Box::from(slack_api.clone()),
+                                                client: &CLIENT,
+                                                smtp: notify::email::Smtp::from_env() });
+    let webhook_log = Box::from(job::webhooks::MemLog::default());
     let slack_access = Box::from(slack_api.clone());
+    let slack_respond = Box::from(slack_api.clone());
     let slack_msg = Box::from(slack_api);
 
     // Git client
@@ -83,7 +184,7 @@ lazy_static::lazy_static! {
     let git = Box::from(git::r#impl::StaticClient);
 
     // Job store
-    let jobs = Box::from(Arc::new(Mutex::new(job::store::StoreData::new())));
+    let jobs = Box::from(JOBS.clone());
 
     // Job executor
     // TODO(orion): does not need to be at this level, could be implementation detail of job store?
@@ -96,6 +197,8 @@ lazy_static::lazy_static! {
     State {
       reqwest_client: &CLIENT,
       api_key,
+      mattermost_command_token,
+      mattermost_action_secret,
       slack_signing_secret,
       slack_client_id,
       slack_client_secret,
@@ -103,8 +206,13 @@ lazy_static::lazy_static! {
       app_reader,
       slack_groups,
       job_messenger,
+      notifier,
+      webhook_log,
       slack_msg,
+      slack_respond,
       slack_access,
+      slack_tokens,
+      slack_installs: slack::install::Used::default(),
       git,
       job_executor,
     }
@@ -30,10 +30,10 @@
 //!
 //! 1. Start a tunnel with `ngrok http 3030` - URL yielded will be referred to as `<ngrok>`
 //! 1. Create a slack app with:
-//!    - Scopes: `['chat:write', 'commands', 'reactions:read']`
+//!    - Bot token scopes: `['chat:write', 'commands', 'files:write', 'reactions:read', 'usergroups:read']`
 //!    - Redirect URI: `<ngrok>/redirect`
 //!    - Slash command: `/deploy` -> `<ngrok>/api/v1/command`
-//! 1. Install to a slack workspace
+//! 1. Install to a slack workspace by visiting `<ngrok>/install`
 //!
 //! # cargo-make
 //! This crate uses [`cargo-make`] for script consistency, in Makefile.toml you'll find:
@@ -62,14 +62,23 @@ use warp::Filter;
 type StateFilter = warp::filters::BoxedFilter<(&'static State,)>;
 
 fn init_job_state_hooks(s: &'static State) {
-  s.jobs.attach_listener(job::hooks::on_create_notify(&s));
-  s.jobs.attach_listener(job::hooks::on_full_approval_change_state(&s));
-  s.jobs.attach_listener(job::hooks::on_full_approval_notify(&s));
-  s.jobs.attach_listener(job::hooks::on_full_approval_deploy(&s));
-  s.jobs.attach_listener(job::hooks::on_failure_log(&s));
-  s.jobs.attach_listener(job::hooks::on_failure_poison(&s));
-  s.jobs.attach_listener(job::hooks::on_poison_notify(&s));
-  s.jobs.attach_listener(job::hooks::on_done_notify(&s));
+  s.jobs.attach_listener(job::hooks::on_create_notify(s));
+  s.jobs.attach_listener(job::hooks::on_create_dm_approvers(s));
+  s.jobs.attach_listener(job::hooks::on_approval_notify(s));
+  s.jobs.attach_listener(job::hooks::on_full_approval_change_state(s));
+  s.jobs.attach_listener(job::hooks::on_full_approval_notify(s));
+  s.jobs.attach_listener(job::hooks::on_full_approval_deploy(s));
+  s.jobs.attach_listener(job::hooks::on_scheduled_deploy(s));
+  s.jobs.attach_listener(job::hooks::on_scheduled_notify(s));
+  s.jobs.attach_listener(job::hooks::on_cancel_notify(s));
+  s.jobs.attach_listener(job::hooks::on_failure_log(s));
+  s.jobs.attach_listener(job::hooks::on_failure_poison(s));
+  s.jobs.attach_listener(job::hooks::on_poison_notify(s));
+  s.jobs.attach_listener(job::hooks::on_done_notify(s));
+  s.jobs.attach_listener(job::hooks::on_done_announce(s));
+  s.jobs.attach_listener(job::hooks::on_change_update_status(s));
+  s.jobs.attach_listener(job::hooks::on_change_notify_sinks(s));
+  s.jobs.attach_listener(job::webhooks::on_event_deliver(s));
 }
 
 fn init_logger() {
@@ -110,6 +119,7 @@ pub async fn main() {
   let api = filters::api(create_state_filter).with(warp::log("mergebot"));
 
   init_job_state_hooks(&STATE);
+  job::reminders::start(&STATE);
 
   Arc::clone(&APP_INIT).wait(); // Wait until worker thread is ready
 
@@ -121,6 +131,7 @@ pub mod filters {
   use std::convert::TryFrom;
 
   use extra::StrExtra;
+  use slack::nonblocking::{AccessAsync, GroupsAsync};
   use warp::{reject::{Reject, Rejection},
              reply::Reply};
 
@@ -153,40 +164,120 @@ pub mod filters {
            .or(oauth_redirect(state))
            .or(command_filter(state))
            .or(event_filter(state))
+           .or(mattermost_command_filter(state))
+           .or(mattermost_action_filter(state))
            .or(get_jobs(state))
+           .or(get_job(state))
+           .or(get_metrics(state))
+           .or(get_webhook_deliveries(state))
            .recover(handle_unauthorized)
   }
 
   fn install(state: fn() -> StateFilter) -> filter!() {
-    warp::path!("install").and(state())
-                          .map(|state| (state, warp::reply::with_status("", http::StatusCode::FOUND)))
-                          .map(|(state, reply): (&'static mergebot::State, warp::reply::WithStatus<&'static str>)| {
-                                 warp::reply::with_header(reply,
-                                                          "Location",
-                                                          slack::authorize_uri(&state.slack_client_id))
-                               })
+    fn redirect(state: &'static State) -> impl Reply {
+      let nonce = slack::install::nonce();
+      let install_state = slack::install::state(&state.slack_client_secret, &nonce, chrono::Utc::now());
+      let location = slack::authorize_uri(&state.slack_client_id, install_state);
+
+      let found = warp::reply::with_header(warp::reply::with_status("", http::StatusCode::FOUND),
+                                           "Location",
+                                           location);
+
+      warp::reply::with_header(found, "Set-Cookie", slack::install::set_cookie(&nonce))
+    }
+
+    warp::path!("install").and(state()).map(redirect)
+  }
+
+  const INSTALL_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>mergebot - {title}</title>
+    <style>body { font-family: sans-serif; max-width: 40em; margin: 4em auto; }</style>
+  </head>
+  <body>
+    <h1>{title}</h1>
+    <p>{detail}</p>
+  </body>
+</html>"#;
+
+  fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+     .replace('<', "&lt;")
+     .replace('>', "&gt;")
+     .replace('"', "&quot;")
+     .replace('\'', "&#39;")
+  }
+
+  fn install_page(status: http::StatusCode, title: &str, detail: &str) -> impl Reply {
+    let html = INSTALL_PAGE.replace("{title}", &escape_html(title))
+                           .replace("{detail}", &escape_html(detail));
+
+    warp::reply::with_status(warp::reply::html(html), status)
   }
 
   fn oauth_redirect(state: fn() -> StateFilter) -> filter!() {
     use serde::{Deserialize, Serialize};
 
+    // slack sends `error` instead of `code` when the user cancels or the install fails
     #[derive(Serialize, Deserialize)]
     struct Params {
-      code: String,
+      code: Option<String>,
+      state: Option<String>,
+      error: Option<String>,
+    }
+
+    async fn handle(state: &'static State, params: Params, nonce: Option<String>) -> Result<impl Reply, Rejection> {
+      let failed = |detail: &str| install_page(http::StatusCode::BAD_REQUEST, "Install failed", detail);
+      let now = chrono::Utc::now();
+
+      // the state has to have come from an install started in this browser, and not have been used already
+      let state_valid = match (&params.state, &nonce) {
+        | (Some(s), Some(nonce)) => {
+          slack::install::state_valid(&state.slack_client_secret, s, nonce, now) && state.slack_installs.redeem(s, now)
+        },
+        | _ => false,
+      };
+
+      if !state_valid {
+        log::info!("install redirect with missing, forged, expired, reused or another browser's state");
+        return Ok(failed("This install link has expired or didn't come from mergebot. Please try installing again."));
+      }
+
+      let code = match (params.code, params.error) {
+        | (_, Some(error)) => return Ok(failed(&format!("Slack didn't install mergebot: {}", error))),
+        | (Some(code), None) => code,
+        | (None, None) => return Ok(failed("Slack didn't send an install code. Please try installing again.")),
+      };
+
+      let access = state.slack_access
+                        .access_async(&code, &state.slack_client_id, &state.slack_client_secret)
+                        .await;
+
+      match access {
+        | Ok(rep) => {
+          let team = match rep.team.name.as_str() {
+            | "" => rep.team.id,
+            | name => name.to_string(),
+          };
+
+          Ok(install_page(http::StatusCode::OK,
+                          "Installed successfully",
+                          &format!("mergebot is installed in {}. You can close this page.", team)))
+        },
+        | Err(e) => {
+          log::error!("{:?}", e);
+          Ok(failed("Slack wouldn't give us access to your workspace. Please try installing again."))
+        },
+      }
     }
 
     warp::path!("redirect").and(state())
                            .and(warp::filters::query::query::<Params>())
-                           .map(|state: &'static State, Params { code }| {
-                             if let Err(e) = state.slack_access
-                                                  .access(&code, &state.slack_client_id, &state.slack_client_secret)
-                             {
-                               log::error!("{:?}", e);
-                               warp::reply::html("<html><body><h1>Install failed</h1></body></html>")
-                             } else {
-                               warp::reply::html("<html><body><h1>Installed successfully</h1></body></html>")
-                             }
-                           })
+                           .and(warp::filters::cookie::optional(slack::install::COOKIE))
+                           .and_then(handle)
+                           .map(|page| warp::reply::with_header(page, "Set-Cookie", slack::install::clear_cookie()))
   }
 
   fn api_key(state: StateFilter) -> filter!(()) {
@@ -207,6 +298,42 @@ pub mod filters {
            .map(|state: &'static State| warp::reply::json(&state.jobs.get_all()))
   }
 
+  /// GET api/v1/jobs/:id -> 200 the job, 404 if there isn't one with that id
+  fn get_job(state: fn() -> StateFilter) -> filter!() {
+    state().and(warp::path!("api" / "v1" / "jobs" / String))
+           .and(warp::get())
+           .and(api_key(state()))
+           .map(|state: &'static State, id: String| {
+             match state.jobs.get_all().into_iter().find(|j| j.id.as_str() == id) {
+               | Some(job) => warp::reply::with_status(warp::reply::json(&job), http::StatusCode::OK),
+               | None => warp::reply::with_status(warp::reply::json(&()), http::StatusCode::NOT_FOUND),
+             }
+           })
+  }
+
+  /// GET api/v1/metrics -> 200 hit rate of the slack group member cache
+  fn get_metrics(state: fn() -> StateFilter) -> filter!() {
+    state().and(warp::path!("api" / "v1" / "metrics"))
+           .and(warp::get())
+           .and(api_key(state()))
+           .map(|state: &'static State| {
+             let stats = state.slack_groups.cache_stats();
+
+             warp::reply::json(&serde_json::json!({
+                                 "slack_groups_cache": stats,
+                                 "slack_groups_cache_hit_rate": stats.map(|s| s.hit_rate()),
+                               }))
+           })
+  }
+
+  /// GET api/v1/webhooks/deliveries -> 200 the most recent attempts to deliver job events to webhooks
+  fn get_webhook_deliveries(state: fn() -> StateFilter) -> filter!() {
+    state().and(warp::path!("api" / "v1" / "webhooks" / "deliveries"))
+           .and(warp::get())
+           .and(api_key(state()))
+           .map(|state: &'static State| warp::reply::json(&state.webhook_log.deliveries()))
+  }
+
   /// <https://api.slack.com/authentication/verifying-requests-from-slack>
   fn slack_request_authentic(mergebot_state: StateFilter) -> filter!((bytes::Bytes,), Rejection) {
     mergebot_state.and(warp::filters::body::bytes())
@@ -227,27 +354,64 @@ pub mod filters {
                                                 .map(|name| format!("hello, {}!", name))
   }
 
-  fn handle_approval(state: &'static State, job: &job::Job<job::StateInit>, user_id: &str) {
+  /// Does a user belong to a group? Logs and yields `false` if slack can't tell us.
+  async fn user_in_group(state: &'static State, team_id: &str, group_id: &str, user_id: &str) -> bool {
+    state.slack_groups
+         .contains_user_async(team_id, group_id, user_id)
+         .await
+         .map_err(|e| log::error!("{:#?}", e))
+         .unwrap_or(false)
+  }
+
+  async fn handle_approval(state: &'static State, job: job::Job<job::StateInit>, user_id: String) {
     use deploy::User;
 
-    let user_in_group = |group_id: &str| {
-      state.slack_groups
-           .contains_user(&job.app.team_id, group_id, user_id)
-           .map_err(|e| log::error!("{:#?}", e))
-           .unwrap_or(false)
-    };
+    let mut user = None;
 
-    let user = job.outstanding_approvers().into_iter().find(|u| match u {
-                                                        | User::User { user_id: u_id, .. } => u_id == user_id,
-                                                        | User::Group { group_id, .. } => user_in_group(&group_id),
-                                                      });
+    for u in job.outstanding_approvers() {
+      let matches = match &u {
+        | User::User { user_id: u_id, .. } => u_id == &user_id,
+        | User::Group { group_id, .. } => user_in_group(state, &job.app.team_id, group_id, &user_id).await,
+      };
 
-    if user.is_none() {
-      log::debug!("(job {:?}) user {} approved but isn't an approver", job.id, user_id);
+      if matches {
+        user = Some(u);
+        break;
+      }
     }
 
-    if let Some(user) = user {
-      state.jobs.approved(&job.id, user);
+    match user {
+      // job listeners may talk to slack, so mutate the store off of the async worker thread
+      | Some(user) => drop(tokio::task::spawn_blocking(move || state.jobs.approved(&job.id, user))),
+      | None => log::debug!("(job {:?}) user {} approved but isn't an approver", job.id, user_id),
+    }
+  }
+
+  async fn handle_cancel(state: &'static State, job: job::Job<job::StateScheduled>, user_id: String) {
+    use deploy::User;
+
+    let mut is_approver = false;
+
+    for u in job.app.users(&job.command.env_name) {
+      is_approver = match u {
+        | User::User { user_id: u_id,
+                       approver: true, } => u_id == user_id,
+        | User::Group { group_id, .. } => user_in_group(state, &job.app.team_id, &group_id, &user_id).await,
+        | _ => false,
+      };
+
+      if is_approver {
+        break;
+      }
+    }
+
+    if job.command.user_id == user_id || is_approver {
+      // job listeners may talk to slack, so mutate the store off of the async worker thread
+      drop(tokio::task::spawn_blocking(move || state.jobs.cancelled(&job.id, &user_id)));
+    } else {
+      log::debug!("(job {:?}) user {} tried to cancel but isn't the requester or an approver",
+                  job.id,
+                  user_id);
     }
   }
 
@@ -258,7 +422,9 @@ pub mod filters {
   async fn handle_event(body: bytes::Bytes,
                         state: &'static State)
                         -> Result<warp::reply::WithStatus<String>, warp::reject::Rejection> {
-    use slack::event::{Event, EventPayload::ReactionAdded, ReactionAddedItem as Item};
+    use slack::event::{Event,
+                       EventPayload::{AppUninstalled, ReactionAdded, SubteamMembersChanged, TokensRevoked},
+                       ReactionAddedItem as Item};
 
     let ev = match serde_json::from_slice::<Event>(&body) {
       | Ok(b) => b,
@@ -275,6 +441,23 @@ pub mod filters {
                          ReactionAdded { user,
                                          reaction,
                                          item: Item::Message { channel, ts }, }, } => {
+        if reaction.as_str() == "x" {
+          let matched_job =
+            state.jobs
+                 .get_all_scheduled()
+                 .into_iter()
+                 .find(|j| match j.state.prev.prev.msg_id.as_ref() {
+                   | Some(msg_id) => j.app.team_id == team_id && msg_id.equals(&channel, &ts),
+                   | _ => false,
+                 });
+
+          if let Some(j) = matched_job {
+            handle_cancel(state, j, user).await;
+          }
+
+          return Ok(ok(String::new()));
+        }
+
         if reaction.as_str() != "+1" {
           return Ok(ok(String::new()));
         }
@@ -288,7 +471,43 @@ pub mod filters {
                                });
 
         if let Some(j) = matched_job {
-          handle_approval(state, &j, &user);
+          handle_approval(state, j, user).await;
+        }
+
+        Ok(ok(String::new()))
+      },
+      | Event::Event { team_id,
+                       event: SubteamMembersChanged { subteam_id }, } => {
+        state.slack_groups.invalidate(&team_id, &subteam_id);
+        Ok(ok(String::new()))
+      },
+      | Event::Event { team_id,
+                       event: AppUninstalled, } => {
+        log::info!("uninstalled from {}, forgetting its token", team_id);
+
+        if let Err(e) = state.slack_tokens.revoke(&team_id) {
+          log::error!("couldn't forget token for {} {:?}", team_id, e);
+        }
+
+        Ok(ok(String::new()))
+      },
+      | Event::Event { team_id,
+                       event: TokensRevoked { tokens }, } => {
+        // slack also tells us when users revoke tokens they granted other apps in the workspace
+        let ours = state.slack_tokens
+                        .rep(&team_id)
+                        .map(|rep| rep.filter(|rep| tokens.bot.contains(&rep.bot_user_id)));
+
+        match ours {
+          | Ok(Some(_)) => {
+            log::info!("token for {} revoked, forgetting it", team_id);
+
+            if let Err(e) = state.slack_tokens.revoke(&team_id) {
+              log::error!("couldn't forget token for {} {:?}", team_id, e);
+            }
+          },
+          | Ok(None) => (),
+          | Err(e) => log::error!("couldn't check if revoked tokens for {} are ours {:?}", team_id, e),
         }
 
         Ok(ok(String::new()))
@@ -309,6 +528,8 @@ pub mod filters {
 
   // [0] - App ensures slack request is authentic
   // [1] - User A issues `/deploy foo staging`
+  // [1.5] - mergebot acknowledges the command right away, then does the rest in the background
+  //         and replies through the command's `response_url`
   // [2] - mergebot checks Apps (configured via `./deployables.json`, which is ignored from source control) for name == "foo"
   // [3] - mergebot checks `foo.repos` for `environments` matching the name "staging"
   // [4] - mergebot ensures User A is in `staging.users`
@@ -328,83 +549,355 @@ pub mod filters {
   async fn handle_command(body: bytes::Bytes,
                           mergebot: &'static State)
                           -> Result<warp::reply::WithStatus<String>, warp::reject::Rejection> {
+    let bad_req = || warp::reply::with_status(String::new(), http::StatusCode::BAD_REQUEST);
+
+    // slack gives up on commands that aren't acknowledged within 3 seconds,
+    // and reading apps, expanding groups & notifying approvers can take longer.
+    let ack = |slash: slack::SlashCommand| {
+      tokio::task::spawn_blocking(move || respond_to_command(mergebot, slash));
+      warp::reply::with_status(String::new(), http::StatusCode::OK)
+    };
+
+    serde_urlencoded::from_bytes::<slack::SlashCommand>(&body).tap_err(|e| log::error!("{:#?}", e))
+                                                              .map(ack)
+                                                              .and_then_err(|_| Ok(bad_req()))
+  }
+
+  /// Initiate a deployment from mattermost
+  fn mattermost_command_filter(state: fn() -> StateFilter) -> filter!((impl Reply,)) {
+    warp::path!("api" / "v1" / "mattermost" / "command").and(warp::post())
+                                                        .and(warp::filters::body::bytes())
+                                                        .and(state())
+                                                        .and_then(handle_mattermost_command)
+  }
+
+  async fn handle_mattermost_command(body: bytes::Bytes,
+                                     mergebot: &'static State)
+                                     -> Result<warp::reply::WithStatus<String>, warp::reject::Rejection> {
+    let slash = match serde_urlencoded::from_bytes::<mattermost::SlashCommand>(&body) {
+      | Ok(slash) => slash,
+      | Err(e) => {
+        log::error!("{:#?}", e);
+        return Ok(warp::reply::with_status(String::new(), http::StatusCode::BAD_REQUEST));
+      },
+    };
+
+    let authentic = mergebot.mattermost_command_token
+                            .as_ref()
+                            .map(|token| mattermost::token_authentic(token, &slash.token))
+                            .unwrap_or(false);
+
+    if !authentic {
+      return Err(warp::reject::custom(Unauthorized));
+    }
+
+    // mattermost takes responses through `response_url` just like slack,
+    // so the rest of the flow is the same
+    tokio::task::spawn_blocking(move || respond_to_command(mergebot, slash.into()));
+
+    Ok(ok(String::new()))
+  }
+
+  /// Approve or cancel a job from the buttons on our mattermost posts
+  fn mattermost_action_filter(state: fn() -> StateFilter) -> filter!((impl Reply,)) {
+    warp::path!("api" / "v1" / "mattermost" / "action").and(warp::post())
+                                                       .and(warp::filters::body::json())
+                                                       .and(state())
+                                                       .and_then(handle_mattermost_action)
+  }
+
+  async fn handle_mattermost_action(action: mattermost::Action,
+                                    state: &'static State)
+                                    -> Result<impl Reply, warp::reject::Rejection> {
+    use mattermost::ActionKind;
+
+    let authentic = state.mattermost_action_secret
+                         .as_ref()
+                         .map(|secret| mattermost::token_authentic(secret, &action.context.secret))
+                         .unwrap_or(false);
+
+    if !authentic {
+      return Err(warp::reject::custom(Unauthorized));
+    }
+
+    let mattermost::Action { user_id, context, .. } = action;
+    let is_job =
+      |id: &job::Id, app: &deploy::App| id.as_str() == context.job_id && app.chat == deploy::Chat::Mattermost;
+
+    match context.action {
+      | ActionKind::Approve => {
+        let job = state.jobs.get_all_new().into_iter().find(|j| is_job(&j.id, &j.app));
+
+        if let Some(job) = job {
+          handle_approval(state, job, user_id).await;
+        }
+      },
+      | ActionKind::Cancel => {
+        let job = state.jobs
+                       .get_all_scheduled()
+                       .into_iter()
+                       .find(|j| is_job(&j.id, &j.app));
+
+        if let Some(job) = job {
+          handle_cancel(state, job, user_id).await;
+        }
+      },
+    }
+
+    // mattermost wants JSON back, even if we've nothing to say
+    Ok(warp::reply::json(&serde_json::json!({})))
+  }
+
+  /// Validate and carry out a slash command, then tell the user how it went through the command's `response_url`
+  fn respond_to_command(mergebot: &'static State, slash: slack::SlashCommand) {
     use deploy::User;
+    use slack::respond::Response;
 
-    let try_create_job = |(cmd, app): (deploy::Command, _)| {
+    let try_create_job = |(cmd, app): (deploy::Command, deploy::App)| {
+      // compare apps by name, since jobs loaded from the job store don't keep their app's webhooks & sinks
       let existing = mergebot.jobs.get_all().into_iter().find(|j| {
                                                           j.state.in_progress()
-                                                          && j.app == app
+                                                          && j.app.team_id == app.team_id
+                                                          && j.app.name == app.name
                                                           && j.command.env_name.loose_eq(&cmd.env_name)
                                                         });
 
       if let Some(job) = existing {
-        Err(deploy::Error::JobAlreadyQueued(job))
+        Err(deploy::Error::JobAlreadyQueued(Box::new(job)))
       } else {
         Ok(mergebot.jobs.create(app, cmd)).map(|id| mergebot.jobs.get_new(&id).unwrap())
       }
     };
 
-    let bad_req = || warp::reply::with_status(String::new(), http::StatusCode::BAD_REQUEST);
-    let failed = |e| {
-      let msg = match e {
-        | deploy::Error::JobAlreadyQueued(job) => format!("There's already a {} deploy in progress for {}",
-                                                          job.command.env_name, job.app.name),
-        | deploy::Error::EnvNotFound(app, env) => format!("I couldn't find an app named {}", app),
-        | _ => {
-          let uh_oh = "Uh oh :confused: I wasn't able to do that.";
-          let link = "https://github.com/cakekindel/mergebot/issues";
-
-          format!("{} <{}|Please file an issue> and let Orion know there's a bug!",
-                  uh_oh, link)
-        },
-      };
+    let user_is = |app: &deploy::App, user_id: &str, user: &User| match user {
+      | User::User { user_id: id, .. } => id == user_id,
+      | User::Group { group_id, .. } => mergebot.slack_groups
+                                                .contains_user(&app.team_id, group_id, user_id)
+                                                .tap_err(|e| log::error!("{:?}", e))
+                                                .unwrap_or(false),
+    };
 
-      warp::reply::with_status(msg, http::StatusCode::OK)
+    let user_can_deploy = |app: &deploy::App, env: &deploy::app::Mergeable, user_id: &str| {
+      env.users.iter().any(|u| user_is(app, user_id, u))
     };
 
     let user_matches = |app: &deploy::App, cmd: &deploy::Command| {
-      app.repos.iter().flat_map(|r| r.environments.iter()).any(|env| {
-                                                            env.users.iter().any(|u| match u {
-                                                                              | User::User { user_id, .. } => {
-                                                                                user_id == &cmd.user_id
-                                                                              },
-                                                                              | User::Group { group_id, .. } => {
-                                                                                mergebot.slack_groups
-                                                                                        .contains_user(&app.team_id,
-                                                                                                       &group_id,
-                                                                                                       &cmd.user_id)
-                                                                                        .tap_err(|e| {
-                                                                                          log::error!("{:?}", e)
-                                                                                        })
-                                                                                        .unwrap_or(false)
-                                                                              },
-                                                                            })
-                                                          })
+      app.repos
+         .iter()
+         .flat_map(|r| r.environments.iter().filter(|env| env.name_eq(&cmd.env_name)))
+         .any(|env| user_can_deploy(app, env, &cmd.user_id))
+    };
+
+    let user_is_approver = |app: &deploy::App, cmd: &deploy::Command| {
+      app.users(&cmd.env_name)
+         .iter()
+         .any(|u| u.is_approver() && user_is(app, &cmd.user_id, u))
+    };
+
+    // [4.5] - mergebot ensures the environment isn't frozen or outside its deploy windows
+    //         (at the scheduled time, if scheduled), unless an approver asked to override
+    let check_allowed = |(cmd, app): (deploy::Command, deploy::App)| match cmd.override_freeze {
+      | true if user_is_approver(&app, &cmd) => Ok((cmd, app)),
+      | true => Err(deploy::Error::OverrideForbidden),
+      | false => app.allowed_at(&cmd.env_name, cmd.scheduled_for.unwrap_or_else(chrono::Utc::now))
+                    .map_err(deploy::Error::Blocked)
+                    .map(|_| (cmd, app)),
+    };
+
+    // [2.5] - for `/deploy foo promote`, mergebot picks the stage of foo's promotion chain
+    //         after the one deployed most recently
+    let pick_stage = |cmd: deploy::Command| {
+      mergebot.app_reader
+              .get_app(&cmd.team_id, &cmd.app_name)
+              .and_then(|app| {
+                let deployed = app.promotion
+                                  .iter()
+                                  .filter_map(|stage| mergebot.jobs.get_last_done(&app.team_id, &app.name, stage))
+                                  .max_by_key(|job| job.state.finished_at())
+                                  .map(|job| job.command.env_name);
+
+                match app.next_stage(deployed.as_deref()) {
+                  | _ if app.promotion.is_empty() => Err(deploy::Error::NoPromotionChain(app.name.clone())),
+                  | Some(stage) => Ok(deploy::Command { env_name: stage.to_string(),
+                                                        ..cmd }),
+                  | None => Err(deploy::Error::NothingToPromote(app.name.clone())),
+                }
+              })
     };
 
     let user_didnt_match = |cmd: &deploy::Command| {
       log::info!("user does not have access to app: {:?}", cmd);
-      deploy::Error::EnvNotFound(cmd.app_name.clone(), cmd.env_name.clone())
+      deploy::Error::NotAllowed(cmd.app_name.clone(), cmd.env_name.clone())
     };
 
-    serde_urlencoded::from_bytes::<slack::SlashCommand>(&body).tap_err(|e| log::error!("{:#?}", e))
-                                                              .map(|slash| {
-                                                                deploy::Command::try_from(slash).and_then(|cmd| {
-                                                                  mergebot.app_reader
-                                                                          .get_matching_cmd(&cmd)
-                                                                          .filter(|app| user_matches(app, &cmd),
-                                                                                  |_| user_didnt_match(&cmd))
-                                                                          .map(|app| (cmd, app))
-                                                                })
-                                                                .and_then(try_create_job)
-                                                                .map(|_| {
-                                                                  warp::reply::with_status(String::new(),
-                                                                                           http::StatusCode::OK)
-                                                                })
-                                                                .tap_err(|e| log::error!("{:?}", e))
-                                                                .map_err(failed)
-                                                                .unwrap_or_else(|e| e)
-                                                              })
-                                                              .and_then_err(|_| Ok(bad_req()))
+    // [4.75] - mergebot ensures nobody has locked the environment
+    let check_locked =
+      |(cmd, app): (deploy::Command, deploy::App)| match mergebot.jobs.get_lock(&app.team_id, &app.name, &cmd.env_name)
+      {
+        | Some(lock) => Err(deploy::Error::Locked(Box::new(lock))),
+        | None => Ok((cmd, app)),
+      };
+
+    let find_app = |cmd: deploy::Command| {
+      mergebot.app_reader
+              .get_matching_cmd(&cmd)
+              .filter(|app| user_matches(app, &cmd), |_| user_didnt_match(&cmd))
+              .map(|app| (cmd, app))
+    };
+
+    let request_deploy = |cmd: deploy::Command| {
+      // the executor makes sure the previous stage of the app's promotion chain has been deployed,
+      // since it's the one that can look at the previous stage's branch in git
+      find_app(cmd).and_then(check_allowed)
+                   .and_then(check_locked)
+                   .and_then(try_create_job)
+                   .map(|job| {
+                     format!("Requested a deploy of {} to {}. I've asked for approval in <#{}>.",
+                             job.app.name,
+                             job.command.env_name,
+                             job.app.notification_channel(&job.command.env_name))
+                   })
+    };
+
+    // Anyone who can deploy to an environment can lock it, e.g. during an incident
+    let lock = |(cmd, app): (deploy::Command, deploy::App), reason: Option<String>| {
+      let lock = deploy::Lock { team_id: app.team_id.clone(),
+                                app_name: app.name.clone(),
+                                env_name: cmd.env_name.clone(),
+                                user_id: cmd.user_id,
+                                reason,
+                                at: chrono::Utc::now() };
+
+      match mergebot.jobs.lock(lock) {
+        | Some(existing) => Err(deploy::Error::Locked(Box::new(existing))),
+        | None => Ok(format!(":lock: Locked {app} {env}. Nothing can be deployed to it until someone runs \
+                              `/deploy unlock {app} {env}`.",
+                             app = app.name,
+                             env = cmd.env_name)),
+      }
+    };
+
+    let unlock =
+      |(cmd, app): (deploy::Command, deploy::App)| match mergebot.jobs.unlock(&app.team_id, &app.name, &cmd.env_name) {
+        | Some(lock) => {
+          // deploys that were held by the lock can go ahead now
+          mergebot.jobs
+                  .get_all_scheduled()
+                  .into_iter()
+                  .filter(|j| j.state.held_by_lock() && lock.matches(&j.app.team_id, &j.app.name, &j.command.env_name))
+                  .for_each(|j| mergebot.job_executor.schedule_exec_later(&j));
+
+          Ok(format!(":unlock: Unlocked {} {}.", app.name, cmd.env_name))
+        },
+        | None => Err(deploy::Error::NotLocked(app.name, cmd.env_name)),
+      };
+
+    let jobs_matching = |query: &deploy::Query| {
+      let mut jobs = mergebot.jobs
+                             .get_all()
+                             .into_iter()
+                             .filter(|j| query.matches(&j.app.team_id, &j.app.name, &j.command.env_name))
+                             .collect::<Vec<_>>();
+
+      jobs.sort_by_key(|j| std::cmp::Reverse(j.created_at));
+      jobs
+    };
+
+    let status = |query: deploy::Query| {
+      let locks = mergebot.jobs
+                          .get_all_locks()
+                          .into_iter()
+                          .filter(|l| query.matches(&l.team_id, &l.app_name, &l.env_name))
+                          .map(|l| format!(":lock: {}", l));
+
+      let jobs = jobs_matching(&query).into_iter()
+                                      .filter(|j| j.state.in_progress())
+                                      .map(|j| job::status::describe(&j, &query.team_domain));
+
+      let lines = locks.chain(jobs).collect::<Vec<_>>();
+
+      match lines.is_empty() {
+        | true => String::from("Nothing is locked or in progress."),
+        | false => lines.join("\n"),
+      }
+    };
+
+    let list = |query: deploy::Query| {
+      let lines = jobs_matching(&query).into_iter()
+                                       .take(job::status::RECENT_JOBS)
+                                       .map(|j| job::status::describe(&j, &query.team_domain))
+                                       .collect::<Vec<_>>();
+
+      match lines.is_empty() {
+        | true => String::from("Nothing has been deployed yet."),
+        | false => lines.join("\n"),
+      }
+    };
+
+    let apps = |query: deploy::Query| {
+      mergebot.app_reader
+              .read()
+              .map_err(deploy::Error::ReadingApps)
+              .map(|apps| {
+                let lines =
+                  apps.iter()
+                      .filter(|app| app.team_id == query.team_id)
+                      .filter_map(|app| {
+                        let mut envs: Vec<&str> = vec![];
+
+                        for env in app.repos.iter().flat_map(|r| r.environments.iter()) {
+                          if !envs.iter().any(|name| env.name_eq(name)) && user_can_deploy(app, env, &query.user_id) {
+                            envs.push(&env.name);
+                          }
+                        }
+
+                        match envs.is_empty() {
+                          | true => None,
+                          | false => Some(format!("• *{}*: {}", app.name, envs.join(", "))),
+                        }
+                      })
+                      .collect::<Vec<_>>();
+
+                match lines.is_empty() {
+                  | true => String::from("There aren't any apps you can deploy."),
+                  | false => lines.join("\n"),
+                }
+              })
+    };
+
+    let notify = |prefs: deploy::NotifyPrefs| {
+      let reply = match prefs.dm {
+        | true => "I'll DM you when a deploy needs your approval.",
+        | false => "I'll only ask for your approval in each app's channel.",
+      };
+
+      mergebot.jobs.set_notify_prefs(prefs);
+      String::from(reply)
+    };
+
+    // Replies go to the command's `response_url` so they can be Block Kit,
+    // and so lock changes can be announced to the channel
+    let act = |action: deploy::Action| match action {
+      | deploy::Action::Deploy(cmd) => request_deploy(cmd).map(Response::ephemeral),
+      | deploy::Action::Promote(cmd) => pick_stage(cmd).and_then(request_deploy).map(Response::ephemeral),
+      | deploy::Action::Lock(cmd, reason) => find_app(cmd).and_then(|found| lock(found, reason))
+                                                          .map(Response::in_channel),
+      | deploy::Action::Unlock(cmd) => find_app(cmd).and_then(unlock).map(Response::in_channel),
+      | deploy::Action::Status(query) => Ok(Response::ephemeral(status(query))),
+      | deploy::Action::List(query) => Ok(Response::ephemeral(list(query))),
+      | deploy::Action::Apps(query) => apps(query).map(Response::ephemeral),
+      | deploy::Action::Notify(prefs) => Ok(Response::ephemeral(notify(prefs))),
+      | deploy::Action::Help => Ok(Response::ephemeral(deploy::help())),
+    };
+
+    let response_url = slash.response_url.clone();
+    let response = deploy::Action::try_from(slash).and_then(act)
+                                                  .tap_err(|e| log::error!("{:?}", e))
+                                                  .unwrap_or_else(Response::ephemeral);
+
+    mergebot.slack_respond
+            .respond(&response_url, &response)
+            .tap_err(|e| log::error!("{:?}", e))
+            .ok();
   }
 }
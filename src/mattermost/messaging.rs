@@ -0,0 +1,311 @@
+use chrono::{DateTime, Utc};
+
+use super::{posts::{Button, Post, Posts},
+            ActionKind,
+            Api};
+use crate::{deploy::{self, User},
+            job::{self, Job, State},
+            slack};
+
+/// Mattermost doesn't localize times like slack does
+fn fmt_time(at: &DateTime<Utc>) -> String {
+  at.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+fn msg_id(post: Post) -> slack::msg::Id {
+  slack::msg::Id { channel: post.channel_id,
+                   ts: post.id }
+}
+
+/// ID of the request post for a job, if it was sent
+fn request_id<S: State>(job: &Job<S>) -> slack::Result<slack::msg::Id> {
+  job.state
+     .clone()
+     .into_states()
+     .msg_id()
+     .cloned()
+     .ok_or_else(|| slack::Error::Other(String::from("no message to respond to")))
+}
+
+fn approve(job_id: &job::Id) -> Button {
+  Button { label: "Approve",
+           action: ActionKind::Approve,
+           job_id: job_id.to_string() }
+}
+
+fn cancel(job_id: &job::Id) -> Button {
+  Button { label: "Cancel",
+           action: ActionKind::Cancel,
+           job_id: job_id.to_string() }
+}
+
+impl Api {
+  /// Mention a user by username, falling back to their id if mattermost can't tell us
+  fn mention_id(&self, user_id: &str) -> String {
+    self.username(user_id)
+        .map(|name| format!("@{}", name))
+        .unwrap_or_else(|_| user_id.to_string())
+  }
+
+  fn mention(&self, user: &User) -> String {
+    match user {
+      | User::User { user_id, .. } => self.mention_id(user_id),
+      | User::Group { group_id, .. } => format!("a member of {}", group_id),
+    }
+  }
+
+  fn mention_all(&self, users: &[User]) -> String {
+    let mut mentions = users.iter().map(|user| self.mention(user)).collect::<Vec<_>>();
+
+    match mentions.pop() {
+      | Some(last) if !mentions.is_empty() => format!("{} & {}", mentions.join(", "), last),
+      | Some(last) => last,
+      | None => String::from("nobody"),
+    }
+  }
+
+  /// Who still needs to approve a job, mentioned
+  fn waiting_on(&self, job: &Job<job::StateInit>) -> Option<String> {
+    let waiting_on = job.outstanding_approvers()
+                        .into_iter()
+                        .filter(User::is_approver)
+                        .collect::<Vec<_>>();
+
+    match waiting_on.is_empty() {
+      | true => None,
+      | false => Some(self.mention_all(&waiting_on)),
+    }
+  }
+
+  fn reply(&self, job_id: slack::Result<slack::msg::Id>, message: &str) -> slack::Result<slack::msg::Id> {
+    let id = job_id?;
+
+    self.create(&id.channel, Some(&id.ts), message, &[])
+        .map(msg_id)
+        .map_err(Into::into)
+  }
+
+  fn job_created_msg(&self, job: &Job<job::StateInit>) -> String {
+    let scheduled = job.command
+                       .scheduled_for
+                       .map(|at| format!(" at {}", fmt_time(&at)))
+                       .unwrap_or_default();
+
+    let mut lines = vec![format!("@here {} has requested a deploy merge for {} to {}{}.",
+                                 self.mention_id(&job.command.user_id),
+                                 job.app.name,
+                                 job.command.env_name,
+                                 scheduled)];
+
+    for repo in &job.app.repos {
+      for env in repo.environments
+                     .iter()
+                     .filter(|env| env.name_eq(&job.command.env_name))
+      {
+        lines.push(format!("- {} changes: {}/compare/{}..{}",
+                           repo.name, repo.human_url, env.target.0, env.base.0));
+      }
+    }
+
+    let mut approvers = job.app
+                           .users(&job.command.env_name)
+                           .into_iter()
+                           .filter(User::is_approver)
+                           .collect::<Vec<_>>();
+    approvers.dedup();
+
+    lines.push(format!("In order to merge, I need {} to click Approve.",
+                       self.mention_all(&approvers)));
+
+    lines.join("\n")
+  }
+
+  fn job_status_msg(&self, job: &Job<job::States>) -> String {
+    let init = job.map_state(|s| s.init().clone());
+
+    let status = match &job.state {
+      | job::States::Init(_) => match self.waiting_on(&init) {
+        | Some(waiting_on) => format!("waiting for approval from {}", waiting_on),
+        | None => String::from("waiting for approval"),
+      },
+      | job::States::Approved(_) => String::from("approved, deploying"),
+      | job::States::Scheduled(s) => match &s.blocked {
+        | Some(blocked) if s.held_by_lock() => format!("approved, held until unlocked: {}", blocked),
+        | Some(blocked) => format!("approved, held until {}: {}", fmt_time(&s.at), blocked),
+        | None => format!("approved, scheduled for {}", fmt_time(&s.at)),
+      },
+      | job::States::Cancelled(s) => format!("cancelled by {}", self.mention_id(&s.by)),
+      | job::States::Errored(s) => format!("failed, retrying {}", fmt_time(&s.next_attempt)),
+      | job::States::Poisoned(_) => String::from("failed"),
+      | job::States::Done(_) => String::from("deployed"),
+    };
+
+    let mut lines = vec![self.job_created_msg(&init),
+                         String::new(),
+                         format!("**Status:** {}", status)];
+
+    if !init.state.approved_by.is_empty() {
+      lines.push(format!("**Approved by:** {}", self.mention_all(&init.state.approved_by)));
+    }
+
+    if let job::States::Done(done) = &job.state {
+      lines.extend(done.merged().iter().map(|m| {
+                                         format!("**{}:** merged `{}..{}`",
+                                                 m.repo,
+                                                 m.prev_target.short(),
+                                                 m.target.short())
+                                       }));
+    }
+
+    lines.join("\n")
+  }
+}
+
+impl job::Messenger for Api {
+  fn send_job_created(&self, job: &Job<job::StateInit>) -> slack::Result<slack::msg::Id> {
+    self.create(job.app.notification_channel(&job.command.env_name),
+                None,
+                &self.job_created_msg(job),
+                &[approve(&job.id)])
+        .map(msg_id)
+        .map_err(Into::into)
+  }
+
+  fn send_approval_dm(&self,
+                      job: &Job<job::StateInit>,
+                      user_id: &str,
+                      reminder: bool)
+                      -> slack::Result<slack::msg::Id> {
+    let text = match reminder {
+      | true => format!(":wave: Reminder: {}'s deploy of {} to {} is still waiting on your approval.",
+                        self.mention_id(&job.command.user_id),
+                        job.app.name,
+                        job.command.env_name),
+      | false => format!("{} requested a deploy of {} to {} and needs your approval.",
+                         self.mention_id(&job.command.user_id),
+                         job.app.name,
+                         job.command.env_name),
+    };
+
+    self.direct_channel(user_id)
+        .and_then(|channel| self.create(&channel, None, &text, &[approve(&job.id)]))
+        .map(msg_id)
+        .map_err(Into::into)
+  }
+
+  fn send_approval_reminder(&self, job: &Job<job::StateInit>) -> slack::Result<slack::msg::Id> {
+    let waiting_on = self.waiting_on(job).unwrap_or_else(|| String::from("nobody"));
+
+    self.reply(request_id(job),
+               &format!(":bell: This deploy is still waiting on {} to approve it.", waiting_on))
+  }
+
+  fn send_job_approved_by(&self, job: &Job<job::StateInit>, user: &deploy::User) -> slack::Result<slack::msg::Id> {
+    let remaining = match self.waiting_on(job) {
+      | Some(waiting_on) => format!("Still waiting on {}.", waiting_on),
+      | None => String::from("That's everyone!"),
+    };
+
+    self.reply(request_id(job),
+               &format!("{} approved :+1: {}", self.mention(user), remaining))
+  }
+
+  fn send_job_approved(&self, job: &Job<job::StateApproved>) -> slack::Result<slack::msg::Id> {
+    self.reply(request_id(job),
+               &format!("Merge approved! :sunglasses: Let's go to {} :rocket:",
+                        job.command.env_name))
+  }
+
+  fn send_job_failed(&self, job: &Job<job::StatePoisoned>) -> slack::Result<slack::msg::Id> {
+    let mut attempts = job.map_state(|s| s.prev).flatten_errors();
+    attempts.reverse();
+
+    let mut lines = vec![format!("Merge failed after {} attempts :skull_and_crossbones:", attempts.len())];
+
+    for (ix, attempt) in attempts.iter().enumerate() {
+      lines.push(format!("**Attempt {}** failed {}:", ix + 1, fmt_time(&attempt.at)));
+      lines.extend(attempt.errs.iter().map(|e| format!("- {}", e)));
+    }
+
+    self.reply(request_id(job), &lines.join("\n"))
+  }
+
+  fn send_job_done(&self, job: &Job<job::StateDone>) -> slack::Result<slack::msg::Id> {
+    let mut lines = vec![String::from("Deploy merge succeeded! :rocket:")];
+
+    for merged in job.state.merged().iter().filter(|m| !m.commits.is_empty()) {
+      lines.push(format!("\n**{}**", merged.repo));
+      lines.extend(merged.commits.iter().map(|c| match c.pr {
+                                          | Some(pr) => format!("- {} (#{}) - {}", c.subject, pr, c.author),
+                                          | None => format!("- {} - {}", c.subject, c.author),
+                                        }));
+    }
+
+    self.reply(request_id(job), &lines.join("\n"))
+  }
+
+  fn send_job_announcement(&self, job: &Job<job::StateDone>, channel_id: &str) -> slack::Result<slack::msg::Id> {
+    let request = match request_id(job) {
+      | Ok(id) if !job.command.team_domain.is_empty() => {
+        format!(" ([request]({}/{}/pl/{}))",
+                self.base_url, job.command.team_domain, id.ts)
+      },
+      | _ => String::new(),
+    };
+
+    let mut lines = vec![format!(":rocket: {} deployed {} to {}{}.",
+                                 self.mention_id(&job.command.user_id),
+                                 job.app.name,
+                                 job.command.env_name,
+                                 request)];
+
+    lines.extend(job.state.merged().iter().map(|m| {
+                                            format!("- {}: merged `{}..{}`",
+                                                    m.repo,
+                                                    m.prev_target.short(),
+                                                    m.target.short())
+                                          }));
+
+    self.create(channel_id, None, &lines.join("\n"), &[])
+        .map(msg_id)
+        .map_err(Into::into)
+  }
+
+  fn send_job_scheduled(&self, job: &Job<job::StateScheduled>) -> slack::Result<slack::msg::Id> {
+    let held = job.state
+                  .blocked
+                  .as_ref()
+                  .map(|blocked| format!("{}, so ", blocked))
+                  .unwrap_or_default();
+
+    let when = match job.state.held_by_lock() {
+      | true => String::from("once it's unlocked"),
+      | false => format!("at {}", fmt_time(&job.state.at)),
+    };
+
+    self.reply(request_id(job),
+               &format!("{}I'll deploy to {} {} :alarm_clock: Click Cancel on the request to cancel before then.",
+                        held, job.command.env_name, when))
+  }
+
+  fn send_job_cancelled(&self, job: &Job<job::StateCancelled>) -> slack::Result<slack::msg::Id> {
+    self.reply(request_id(job),
+               &format!("{} cancelled this deploy :no_entry_sign:",
+                        self.mention_id(&job.state.by)))
+  }
+
+  fn update_job_status(&self, job: &Job<job::States>) -> slack::Result<slack::msg::Id> {
+    let id = request_id(job)?;
+
+    // the request's buttons follow the job through its states
+    let buttons = match &job.state {
+      | job::States::Init(_) => vec![approve(&job.id)],
+      | job::States::Scheduled(_) => vec![cancel(&job.id)],
+      | _ => vec![],
+    };
+
+    self.patch(&id.ts, &self.job_status_msg(job), &buttons)
+        .map(msg_id)
+        .map_err(Into::into)
+  }
+}
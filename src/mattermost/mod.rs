@@ -0,0 +1,198 @@
+use serde::{Deserialize as De, Serialize as Ser};
+
+use crate::slack;
+
+/// Creating & editing posts, and looking up users
+pub mod posts;
+
+/// Notifying approvers through mattermost
+mod messaging;
+
+/// Mattermost API result
+pub type Result<T> = core::result::Result<T, self::Error>;
+
+/// Errors encounterable by the mattermost api
+#[derive(Debug)]
+pub enum Error {
+  /// Error sending, establishing http connection, deserializing, etc.
+  Http(reqwest::Error),
+
+  /// Some other error
+  Other(String),
+}
+
+impl From<Error> for slack::Error {
+  // job messengers speak slack's errors
+  fn from(e: Error) -> Self {
+    match e {
+      | Error::Http(e) => Self::Http(e),
+      | Error::Other(e) => Self::Other(e),
+    }
+  }
+}
+
+/// Represents the real mattermost API, makes HTTP requests
+#[derive(Clone)]
+pub struct Api {
+  base_url: String,
+  token: String,
+  action_url: String,
+  action_secret: String,
+  client: &'static reqwest::blocking::Client,
+}
+
+// keep the bot token out of logs
+impl std::fmt::Debug for Api {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Api")
+     .field("base_url", &self.base_url)
+     .field("action_url", &self.action_url)
+     .finish()
+  }
+}
+
+impl Api {
+  /// Create a new instance.
+  ///
+  /// `action_url` is where mattermost should send button clicks (our `api/v1/mattermost/action`),
+  /// and `action_secret` is sent back with them so we can tell they came from buttons we made.
+  pub fn new(base_url: impl ToString,
+             token: impl ToString,
+             action_url: impl ToString,
+             action_secret: impl ToString,
+             client: &'static reqwest::blocking::Client)
+             -> Self {
+    Self { base_url: base_url.to_string().trim_end_matches('/').to_string(),
+           token: token.to_string(),
+           action_url: action_url.to_string(),
+           action_secret: action_secret.to_string(),
+           client }
+  }
+}
+
+/// Validate a token sent by mattermost against the one we expect.
+///
+/// Compares every byte so that how long it takes doesn't reveal how much of the token was right.
+pub fn token_authentic(expected: &str, token: &str) -> bool {
+  let diff = expected.bytes()
+                     .zip(token.bytes())
+                     .fold(0, |diff, (a, b)| diff | (a ^ b));
+
+  !expected.is_empty() && expected.len() == token.len() && diff == 0
+}
+
+/// Payload sent by mattermost on slash commands.
+///
+/// [https://developers.mattermost.com/integrate/slash-commands/]
+///
+/// Same as slack's, plus the verification token mattermost issued for the command.
+#[derive(Ser, De, Debug, PartialEq)]
+pub struct SlashCommand {
+  /// The command that was typed in, e.g. `/deploy`
+  pub command: String,
+  /// Token mattermost issued when the command was registered
+  pub token: String,
+  /// ID of the channel the command was sent in
+  pub channel_id: String,
+  /// ID of the team the command was sent in
+  pub team_id: String,
+  /// Name of the team the command was sent in (the part of mattermost URLs after the host)
+  pub team_domain: String,
+  /// URL to post responses to
+  pub response_url: String,
+  /// The part of the command after the command itself
+  pub text: String,
+  /// ID of the user who sent the command
+  pub user_id: String,
+}
+
+impl From<SlashCommand> for slack::SlashCommand {
+  fn from(cmd: SlashCommand) -> Self {
+    Self { command: cmd.command,
+           channel_id: cmd.channel_id,
+           team_id: cmd.team_id,
+           response_url: cmd.response_url,
+           team_domain: cmd.team_domain,
+           text: cmd.text,
+           user_id: cmd.user_id }
+  }
+}
+
+/// What a button on one of our posts does
+#[derive(Ser, De, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionKind {
+  /// Approve the job
+  Approve,
+  /// Cancel the job before its scheduled time
+  Cancel,
+}
+
+/// Context we attach to buttons, which mattermost sends back when they're clicked
+#[derive(Ser, De, Debug, PartialEq, Clone)]
+pub struct ActionContext {
+  /// What the button does
+  pub action: ActionKind,
+  /// ID of the job the button is for
+  pub job_id: String,
+  /// Proves the click came from a button we made
+  pub secret: String,
+}
+
+/// Payload sent by mattermost when someone clicks a button on an interactive message.
+///
+/// [https://developers.mattermost.com/integrate/plugins/interactive-messages/]
+#[derive(Ser, De, Debug, PartialEq, Clone)]
+pub struct Action {
+  /// ID of the user who clicked
+  pub user_id: String,
+  /// ID of the team the post is in (empty in direct messages)
+  #[serde(default)]
+  pub team_id: String,
+  /// ID of the post the button is on
+  pub post_id: String,
+  /// Context we attached to the button
+  pub context: ActionContext,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn action_de() {
+    let data = serde_json::json!({
+      "user_id": "rnina9994bde8mua79zqcg5hmo",
+      "user_name": "cakekindel",
+      "channel_id": "h9bx8njdgbbsm8cqg4ye9z9cuy",
+      "channel_name": "deploys",
+      "team_id": "a6bj5sqymtfgjfgpthhbbe1pxo",
+      "team_domain": "orion",
+      "post_id": "gqrnh3675jfxzftnjyjfe4udeh",
+      "trigger_id": "",
+      "type": "",
+      "data_source": "",
+      "context": {
+        "action": "approve",
+        "job_id": "V1StGXR8_Z5jdHi6B-myT",
+        "secret": "shh"
+      }
+    });
+
+    let action = serde_json::from_value::<Action>(data).unwrap();
+
+    assert_eq!(action.user_id, "rnina9994bde8mua79zqcg5hmo");
+    assert_eq!(action.context,
+               ActionContext { action: ActionKind::Approve,
+                               job_id: "V1StGXR8_Z5jdHi6B-myT".into(),
+                               secret: "shh".into() });
+  }
+
+  #[test]
+  fn token() {
+    assert!(token_authentic("abc123", "abc123"));
+    assert!(!token_authentic("abc123", "abc124"));
+    assert!(!token_authentic("abc123", "abc"));
+    assert!(!token_authentic("", ""));
+  }
+}
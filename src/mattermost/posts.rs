@@ -0,0 +1,119 @@
+use serde::{de::DeserializeOwned, Deserialize as De, Serialize as Ser};
+
+use super::{ActionContext, ActionKind, Api, Error, Result};
+
+/// A message in a mattermost channel
+#[derive(Ser, De, PartialEq, Clone, Debug)]
+pub struct Post {
+  /// ID of the post
+  pub id: String,
+  /// ID of the channel it's in
+  pub channel_id: String,
+}
+
+/// A button to put on a post
+#[derive(PartialEq, Clone, Debug)]
+pub struct Button {
+  /// Text on the button
+  pub label: &'static str,
+  /// What clicking it does
+  pub action: ActionKind,
+  /// ID of the job it does it to
+  pub job_id: String,
+}
+
+#[derive(De)]
+struct Named {
+  #[serde(default)]
+  id: String,
+  #[serde(default)]
+  username: String,
+}
+
+/// Create & edit posts
+pub trait Posts: 'static + Sync + Send + std::fmt::Debug {
+  /// Post a message to a channel, or reply in a thread if there's a `root_id`
+  fn create(&self, channel_id: &str, root_id: Option<&str>, message: &str, buttons: &[Button]) -> Result<Post>;
+
+  /// Replace a post's message and buttons
+  fn patch(&self, post_id: &str, message: &str, buttons: &[Button]) -> Result<Post>;
+
+  /// ID of the direct message channel between the bot and a user, creating it if it doesn't exist yet
+  fn direct_channel(&self, user_id: &str) -> Result<String>;
+
+  /// Username of a user, for mentioning them
+  fn username(&self, user_id: &str) -> Result<String>;
+}
+
+impl Api {
+  fn props(&self, buttons: &[Button]) -> serde_json::Value {
+    let actions = buttons.iter()
+                         .map(|button| {
+                           let context = ActionContext { action: button.action,
+                                                         job_id: button.job_id.clone(),
+                                                         secret: self.action_secret.clone() };
+
+                           serde_json::json!({
+                             "id": button.action,
+                             "name": button.label,
+                             "integration": {"url": self.action_url, "context": context},
+                           })
+                         })
+                         .collect::<Vec<_>>();
+
+    match actions.is_empty() {
+      | true => serde_json::json!({ "attachments": [] }),
+      | false => serde_json::json!({ "attachments": [{ "actions": actions }] }),
+    }
+  }
+
+  fn send_base<T: DeserializeOwned>(&self, req: reqwest::blocking::RequestBuilder) -> Result<T> {
+    req.bearer_auth(&self.token)
+       .send()
+       .and_then(|rep| rep.error_for_status())
+       .and_then(|rep| rep.json::<T>())
+       .map_err(Error::Http)
+  }
+}
+
+impl Posts for Api {
+  fn create(&self, channel_id: &str, root_id: Option<&str>, message: &str, buttons: &[Button]) -> Result<Post> {
+    let body = serde_json::json!({
+      "channel_id": channel_id,
+      "root_id": root_id.unwrap_or_default(),
+      "message": message,
+      "props": self.props(buttons),
+    });
+
+    self.send_base(self.client.post(format!("{}/api/v4/posts", self.base_url)).json(&body))
+  }
+
+  fn patch(&self, post_id: &str, message: &str, buttons: &[Button]) -> Result<Post> {
+    let body = serde_json::json!({
+      "message": message,
+      "props": self.props(buttons),
+    });
+
+    self.send_base(self.client
+                       .put(format!("{}/api/v4/posts/{}/patch", self.base_url, post_id))
+                       .json(&body))
+  }
+
+  fn direct_channel(&self, user_id: &str) -> Result<String> {
+    let me = self.send_base::<Named>(self.client.get(format!("{}/api/v4/users/me", self.base_url)))?;
+
+    self.send_base::<Named>(self.client
+                                .post(format!("{}/api/v4/channels/direct", self.base_url))
+                                .json(&[&me.id, user_id]))
+        .map(|channel| channel.id)
+        .and_then(|id| match id.is_empty() {
+          | true => Err(Error::Other(format!("no direct channel with {}", user_id))),
+          | false => Ok(id),
+        })
+  }
+
+  fn username(&self, user_id: &str) -> Result<String> {
+    self.send_base::<Named>(self.client.get(format!("{}/api/v4/users/{}", self.base_url, user_id)))
+        .map(|user| user.username)
+  }
+}
@@ -0,0 +1,70 @@
+use std::env;
+
+use lettre::{message::Mailbox, transport::smtp::authentication::Credentials, Message, SmtpTransport, Transport};
+
+use super::{Error, Note, Result};
+
+/// An SMTP server to send email through
+#[derive(Clone)]
+pub struct Smtp {
+  host: String,
+  username: String,
+  password: String,
+  from: String,
+}
+
+// keep the password out of logs
+impl std::fmt::Debug for Smtp {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Smtp")
+     .field("host", &self.host)
+     .field("username", &self.username)
+     .field("from", &self.from)
+     .finish()
+  }
+}
+
+impl Smtp {
+  /// Create a new instance
+  pub fn new(host: impl ToString, username: impl ToString, password: impl ToString, from: impl ToString) -> Self {
+    Self { host: host.to_string(),
+           username: username.to_string(),
+           password: password.to_string(),
+           from: from.to_string() }
+  }
+
+  /// Read the server from `SMTP_HOST`, `SMTP_USERNAME`, `SMTP_PASSWORD` and `SMTP_FROM`.
+  ///
+  /// `None` if `SMTP_HOST` isn't set.
+  pub fn from_env() -> Option<Self> {
+    let host = env::var("SMTP_HOST").ok().filter(|host| !host.is_empty())?;
+    let var = |name: &str| env::var(name).unwrap_or_else(|_| panic!("{} required when SMTP_HOST is set", name));
+
+    Some(Self::new(host, var("SMTP_USERNAME"), var("SMTP_PASSWORD"), var("SMTP_FROM")))
+  }
+
+  /// Email a note to some addresses
+  pub fn send(&self, to: &[String], note: &Note) -> Result<()> {
+    let email = to.iter()
+                  .try_fold(Message::builder().from(mailbox(&self.from)?), |email, addr| {
+                    Ok(email.to(mailbox(addr)?))
+                  })?
+                  .subject(note.subject.clone())
+                  .body(note.text.clone())
+                  .map_err(|e| Error::Email(e.to_string()))?;
+
+    let creds = Credentials::new(self.username.clone(), self.password.clone());
+    let transport = SmtpTransport::relay(&self.host).map_err(|e| Error::Email(e.to_string()))?
+                                                    .credentials(creds)
+                                                    .build();
+
+    transport.send(&email)
+             .map(|_| ())
+             .map_err(|e| Error::Email(e.to_string()))
+  }
+}
+
+fn mailbox(addr: &str) -> Result<Mailbox> {
+  addr.parse()
+      .map_err(|e| Error::Email(format!("invalid address {:?}: {}", addr, e)))
+}
@@ -0,0 +1,180 @@
+use crate::{deploy, job, slack};
+
+/// Microsoft Teams & Mattermost incoming webhooks
+pub mod webhook;
+
+/// Email over SMTP
+pub mod email;
+
+/// Notifier result
+pub type Result<T> = core::result::Result<T, self::Error>;
+
+/// Errors encounterable sending a note
+#[derive(Debug)]
+pub enum Error {
+  /// Error sending, establishing http connection, etc.
+  Http(reqwest::Error),
+
+  /// Error posting to a slack channel
+  Slack(slack::Error),
+
+  /// Error building or sending an email
+  Email(String),
+
+  /// The sink needs a backend that wasn't configured (name of the missing env var)
+  NotConfigured(&'static str),
+}
+
+/// A job lifecycle message, in a form that isn't specific to any chat service
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+  /// One-line summary, e.g. for email subjects
+  pub subject: String,
+  /// Body of the message. May contain `inline code` and `- ` lists, which all sinks render reasonably.
+  pub text: String,
+}
+
+impl Note {
+  /// Describe a job event, if it's one that sinks are told about.
+  ///
+  /// Individual approvals and retried failures are too chatty to leave slack,
+  /// so sinks never hear about `Approved` or `Errored` events; only the app's notification channel does.
+  pub fn from_event(ev: &job::event::Event<'_>) -> Option<Self> {
+    use job::event::Event;
+
+    let job = ev.job();
+    let (app, env) = (&job.app.name, &job.command.env_name);
+    let note = |what: &str, text: String| Self { subject: format!("Deploy of {} to {} {}", app, env, what),
+                                                 text };
+
+    match ev {
+      | Event::Created(j) => {
+        let at = j.command
+                  .scheduled_for
+                  .map(|at| format!(" for {}", at.format("%Y-%m-%d %H:%M UTC")))
+                  .unwrap_or_default();
+
+        Some(note("requested",
+                  format!("A deploy of {} to {} was requested{}, and is waiting for approval.",
+                          app, env, at)))
+      },
+      | Event::FullyApproved(_) => Some(note("approved", format!("The deploy of {} to {} was approved.", app, env))),
+      | Event::Scheduled(j) => {
+        let held = j.state
+                    .blocked
+                    .as_ref()
+                    .map(|blocked| format!(" {}.", blocked))
+                    .unwrap_or_default();
+
+        let when = match j.state.held_by_lock() {
+          | true => String::from("once it's unlocked"),
+          | false => format!("at {}", j.state.at.format("%Y-%m-%d %H:%M UTC")),
+        };
+
+        Some(note("scheduled",
+                  format!("The deploy of {} to {} will run {}.{}", app, env, when, held)))
+      },
+      | Event::Cancelled(_) => Some(note("cancelled",
+                                         format!("The scheduled deploy of {} to {} was cancelled.", app, env))),
+      | Event::Poisoned(j) => {
+        let attempts = j.map_state(|s| s.prev).flatten_errors().len();
+        let errs = j.state
+                    .prev
+                    .errs
+                    .iter()
+                    .map(|e| format!("\n- {}", e))
+                    .collect::<String>();
+
+        let text = format!("The deploy of {} to {} failed {} times, and won't be retried.",
+                           app, env, attempts);
+
+        Some(note("failed", format!("{} Errors from the last attempt:\n{}", text, errs)))
+      },
+      | Event::Done(j) => {
+        let merged = j.state
+                      .merged()
+                      .iter()
+                      .map(|m| format!("\n- {}: `{}` -> `{}`", m.repo, m.prev_target.short(), m.target.short()))
+                      .collect::<String>();
+
+        Some(note("finished", format!("{} was deployed to {}:\n{}", app, env, merged)))
+      },
+      | Event::Approved(..) | Event::Errored(_) => None,
+    }
+  }
+}
+
+/// Something that can send notes to the sinks an app configures.
+///
+/// Sinks are secondary to the app's notification channel, which is messaged by the job messenger
+/// rather than through a notifier, and only get the events `Note::from_event` describes.
+pub trait Notifier: 'static + Sync + Send + std::fmt::Debug {
+  /// Send a note about one of an app's jobs to one of its sinks
+  fn notify(&self, app: &deploy::App, sink: &deploy::Sink, note: &Note) -> Result<()>;
+}
+
+/// Sends each note with the backend for its kind of sink
+#[derive(Debug)]
+pub struct Backends {
+  /// slack API, for posting to other slack channels
+  pub slack: Box<dyn slack::msg::Messages>,
+  /// HTTP client, for incoming webhooks
+  pub client: &'static reqwest::blocking::Client,
+  /// SMTP server, for email. Email sinks fail if this isn't configured.
+  pub smtp: Option<email::Smtp>,
+}
+
+impl Notifier for Backends {
+  fn notify(&self, app: &deploy::App, sink: &deploy::Sink, note: &Note) -> Result<()> {
+    use deploy::Sink;
+
+    match sink {
+      | Sink::Slack { channel_id } => {
+        use slack_blocks::blox::*;
+
+        let body = format!("*{}*\n{}", note.subject, note.text);
+        let blocks: Vec<slack_blocks::Block> =
+          vec![blox! {<section_block><text kind=mrkdwn>{body}</text></section_block>}.into()];
+
+        self.slack
+            .send(&app.team_id, channel_id, &blocks)
+            .map(|_| ())
+            .map_err(Error::Slack)
+      },
+      | Sink::Teams { url } => webhook::teams(self.client, url, note),
+      | Sink::Mattermost { url } => webhook::mattermost(self.client, url, note),
+      | Sink::Email { to } => match &self.smtp {
+        | Some(smtp) => smtp.send(to, note),
+        | None => Err(Error::NotConfigured("SMTP_HOST")),
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn done_note() {
+    let state = serde_json::json!({
+      "Succeeded": [
+        {"prev": {"msg_id": null, "approved_by": []}},
+        [
+          {
+            "repo": "ui",
+            "prev_target": "0123456789abcdef",
+            "target": "fedcba9876543210",
+            "at": "2021-10-20T12:00:00Z"
+          }
+        ]
+      ]
+    });
+    let job = job::fixture::job::<job::StateDone>(state, vec![]);
+    let note = Note::from_event(&job::event::Event::Done(&job)).unwrap();
+
+    assert_eq!(note.subject, "Deploy of my_app to prod finished");
+    assert_eq!(note.text,
+               "my_app was deployed to prod:\n\n- ui: `0123456` -> `fedcba9`");
+  }
+}
@@ -0,0 +1,32 @@
+use super::{Error, Note, Result};
+
+/// Post a note to a Microsoft Teams incoming webhook, as a message card
+pub fn teams(client: &reqwest::blocking::Client, url: &str, note: &Note) -> Result<()> {
+  let card = serde_json::json!({
+    "@type": "MessageCard",
+    "@context": "https://schema.org/extensions",
+    "summary": note.subject,
+    "title": note.subject,
+    "text": note.text,
+  });
+
+  post(client, url, &card)
+}
+
+/// Post a note to a Mattermost incoming webhook
+pub fn mattermost(client: &reqwest::blocking::Client, url: &str, note: &Note) -> Result<()> {
+  let msg = serde_json::json!({
+    "text": format!("**{}**\n{}", note.subject, note.text),
+  });
+
+  post(client, url, &msg)
+}
+
+fn post(client: &reqwest::blocking::Client, url: &str, body: &serde_json::Value) -> Result<()> {
+  client.post(url)
+        .json(body)
+        .send()
+        .and_then(|rep| rep.error_for_status())
+        .map(|_| ())
+        .map_err(Error::Http)
+}
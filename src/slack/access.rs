@@ -1,8 +1,6 @@
 use serde::{Deserialize as De, Serialize as Ser};
 
-use crate::result_extra::ResultExtra;
-
-#[derive(Debug, Clone, Ser, De, PartialEq)]
+#[derive(Clone, Ser, De, PartialEq)]
 pub struct AccessRep {
   pub access_token: String,
   pub scope: String,
@@ -10,9 +8,28 @@ pub struct AccessRep {
   pub team: Team,
 }
 
+// keep the token out of logs
+impl std::fmt::Debug for AccessRep {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("AccessRep")
+     .field("scope", &self.scope)
+     .field("bot_user_id", &self.bot_user_id)
+     .field("team", &self.team)
+     .finish()
+  }
+}
+
 #[derive(Debug, Clone, Ser, De, PartialEq)]
 pub struct Team {
   pub id: String,
+  #[serde(default)]
+  pub name: String,
+}
+
+#[derive(De)]
+struct AccessRepRaw {
+  ok: bool,
+  error: Option<String>,
 }
 
 pub trait Access: std::fmt::Debug + Send + Sync + 'static {
@@ -28,8 +45,15 @@ impl Access for super::Api {
         .header("authorization", format!("Basic {}", basic))
         .send()
         .and_then(|rep| rep.error_for_status())
-        .and_then(|rep| rep.json::<AccessRep>())
-        .tap(|rep| self.tokens.register(&rep))
+        .and_then(|rep| rep.json::<serde_json::Value>())
         .map_err(super::Error::Http)
+        .and_then(|rep| match serde_json::from_value::<AccessRepRaw>(rep.clone()) {
+          | Ok(AccessRepRaw { ok: true, .. }) => {
+            serde_json::from_value::<AccessRep>(rep).map_err(|e| super::Error::Other(e.to_string()))
+          },
+          | Ok(AccessRepRaw { error, .. }) => Err(super::Error::Slack(error.unwrap_or_else(|| "no error".into()))),
+          | Err(e) => Err(super::Error::Other(e.to_string())),
+        })
+        .and_then(|rep| self.tokens.register(&rep).map(|_| rep).map_err(super::Error::Tokens))
   }
 }
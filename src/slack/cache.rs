@@ -0,0 +1,149 @@
+use std::{collections::HashMap,
+          sync::{atomic::{AtomicU64, Ordering},
+                 Mutex},
+          time::{Duration, Instant}};
+
+use serde::{Deserialize as De, Serialize as Ser};
+
+use super::{groups::Groups, Result};
+use crate::mutex_extra::lock_discard_poison;
+
+/// How often cached lookups were answered without calling slack
+#[derive(Ser, De, PartialEq, Clone, Copy, Debug)]
+pub struct CacheStats {
+  /// Lookups answered from the cache
+  pub hits: u64,
+  /// Lookups that had to call slack
+  pub misses: u64,
+}
+
+impl CacheStats {
+  /// Fraction of lookups answered from the cache, `0.0` if there haven't been any
+  pub fn hit_rate(&self) -> f64 {
+    match self.hits + self.misses {
+      | 0 => 0.0,
+      | total => self.hits as f64 / total as f64,
+    }
+  }
+}
+
+#[derive(Debug)]
+struct Entry {
+  users: Vec<String>,
+  fetched_at: Instant,
+}
+
+/// Groups API decorator that remembers group members for a while,
+/// so that approvals and commands don't fetch `usergroups.users.list` every time.
+///
+/// Entries expire after `ttl`, or sooner when slack tells us
+/// the group's members changed (see `Groups::invalidate`).
+#[derive(Debug)]
+pub struct CachedGroups<G: Groups> {
+  inner: G,
+  ttl: Duration,
+  entries: Mutex<HashMap<(String, String), Entry>>,
+  hits: AtomicU64,
+  misses: AtomicU64,
+}
+
+impl<G: Groups> CachedGroups<G> {
+  /// Wrap a groups API, remembering group members for `ttl`
+  pub fn new(inner: G, ttl: Duration) -> Self {
+    Self { inner,
+           ttl,
+           entries: Mutex::new(HashMap::new()),
+           hits: AtomicU64::new(0),
+           misses: AtomicU64::new(0) }
+  }
+
+  fn cached(&self, key: &(String, String)) -> Option<Vec<String>> {
+    lock_discard_poison(&self.entries).get(key)
+                                      .filter(|entry| entry.fetched_at.elapsed() < self.ttl)
+                                      .map(|entry| entry.users.clone())
+  }
+}
+
+impl<G: Groups> Groups for CachedGroups<G> {
+  fn expand(&self, team_id: &str, group_id: &str) -> Result<Vec<String>> {
+    let key = (team_id.to_string(), group_id.to_string());
+
+    if let Some(users) = self.cached(&key) {
+      self.hits.fetch_add(1, Ordering::Relaxed);
+      return Ok(users);
+    }
+
+    self.misses.fetch_add(1, Ordering::Relaxed);
+
+    // don't hold the lock while we wait on slack
+    let users = self.inner.expand(team_id, group_id)?;
+
+    lock_discard_poison(&self.entries).insert(key,
+                                              Entry { users: users.clone(),
+                                                      fetched_at: Instant::now() });
+
+    Ok(users)
+  }
+
+  fn contains_user(&self, team_id: &str, group_id: &str, user_id: &str) -> Result<bool> {
+    self.expand(team_id, group_id).map(|g| g.iter().any(|u| u == user_id))
+  }
+
+  fn invalidate(&self, team_id: &str, group_id: &str) {
+    lock_discard_poison(&self.entries).remove(&(team_id.to_string(), group_id.to_string()));
+  }
+
+  fn cache_stats(&self) -> Option<CacheStats> {
+    Some(CacheStats { hits: self.hits.load(Ordering::Relaxed),
+                      misses: self.misses.load(Ordering::Relaxed) })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[derive(Debug, Default)]
+  struct GroupsFake {
+    calls: AtomicU64,
+  }
+
+  impl Groups for GroupsFake {
+    fn expand(&self, _: &str, _: &str) -> Result<Vec<String>> {
+      self.calls.fetch_add(1, Ordering::Relaxed);
+      Ok(vec!["U123".into()])
+    }
+
+    fn contains_user(&self, team_id: &str, group_id: &str, user_id: &str) -> Result<bool> {
+      self.expand(team_id, group_id).map(|g| g.iter().any(|u| u == user_id))
+    }
+  }
+
+  #[test]
+  fn caches_until_invalidated() {
+    let groups = CachedGroups::new(GroupsFake::default(), Duration::from_secs(60));
+
+    assert!(groups.contains_user("T123", "G123", "U123").unwrap());
+    assert!(!groups.contains_user("T123", "G123", "U456").unwrap());
+    assert_eq!(groups.inner.calls.load(Ordering::Relaxed), 1);
+
+    groups.invalidate("T123", "G123");
+    groups.expand("T123", "G123").unwrap();
+    assert_eq!(groups.inner.calls.load(Ordering::Relaxed), 2);
+
+    let stats = groups.cache_stats().unwrap();
+    assert_eq!(stats, CacheStats { hits: 1, misses: 2 });
+    assert!((stats.hit_rate() - 1.0 / 3.0).abs() < f64::EPSILON);
+  }
+
+  #[test]
+  fn expires_after_ttl() {
+    let groups = CachedGroups::new(GroupsFake::default(), Duration::from_secs(0));
+
+    groups.expand("T123", "G123").unwrap();
+    groups.expand("T123", "G123").unwrap();
+
+    assert_eq!(groups.inner.calls.load(Ordering::Relaxed), 2);
+    assert_eq!(groups.cache_stats(), Some(CacheStats { hits: 0, misses: 2 }));
+  }
+}
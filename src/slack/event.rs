@@ -34,11 +34,37 @@ pub enum EventPayload {
     /// The item that was reacted to
     item: ReactionAddedItem,
   },
+  /// Members were added to or removed from a user group
+  #[serde(rename = "subteam_members_changed")]
+  SubteamMembersChanged {
+    /// The user group whose members changed
+    subteam_id: String,
+  },
+  /// Some of our tokens for the workspace were revoked
+  #[serde(rename = "tokens_revoked")]
+  TokensRevoked {
+    /// IDs of the users whose tokens were revoked
+    tokens: RevokedTokens,
+  },
+  /// mergebot was uninstalled from the workspace
+  #[serde(rename = "app_uninstalled")]
+  AppUninstalled,
   /// Any other kind of event
   #[serde(other)]
   Other,
 }
 
+/// Users whose tokens were revoked
+#[derive(Ser, De, Debug, PartialEq, Default)]
+pub struct RevokedTokens {
+  /// Users who revoked user tokens
+  #[serde(default)]
+  pub oauth: Vec<String>,
+  /// Bot users whose bot tokens were revoked
+  #[serde(default)]
+  pub bot: Vec<String>,
+}
+
 /// A reaction was added to a message, file, file comment
 #[derive(Ser, De, Debug, PartialEq)]
 #[serde(tag = "type")]
@@ -130,4 +156,74 @@ mod tests {
 
     assert_eq!(expected, actual);
   }
+
+  #[test]
+  pub fn subteam_members_changed_de() {
+    let json = r#"{
+      "token": "XXYYZZ",
+      "team_id": "TXXXXXXXX",
+      "event": {
+        "type": "subteam_members_changed",
+        "subteam_id": "S0614TZR7",
+        "team_id": "TXXXXXXXX",
+        "date_previous_update": 1446670362,
+        "date_update": 1492906952,
+        "added_users": ["U060RNRCZ"],
+        "added_users_count": "1",
+        "removed_users": [],
+        "removed_users_count": "0"
+      },
+      "type": "event_callback"
+    }"#;
+
+    let expected = Event::Event { team_id: "TXXXXXXXX".into(),
+                                  event: EventPayload::SubteamMembersChanged { subteam_id: "S0614TZR7".into() } };
+
+    let actual = serde_json::from_str::<Event>(json).unwrap();
+
+    assert_eq!(expected, actual);
+  }
+
+  #[test]
+  pub fn tokens_revoked_de() {
+    let json = r#"{
+      "token": "XXYYZZ",
+      "team_id": "TXXXXXXXX",
+      "event": {
+        "type": "tokens_revoked",
+        "tokens": {
+          "bot": ["UXXXXXXXX"]
+        }
+      },
+      "type": "event_callback"
+    }"#;
+
+    let tokens = RevokedTokens { oauth: vec![],
+                                 bot: vec!["UXXXXXXXX".into()] };
+    let expected = Event::Event { team_id: "TXXXXXXXX".into(),
+                                  event: EventPayload::TokensRevoked { tokens } };
+
+    let actual = serde_json::from_str::<Event>(json).unwrap();
+
+    assert_eq!(expected, actual);
+  }
+
+  #[test]
+  pub fn app_uninstalled_de() {
+    let json = r#"{
+      "token": "XXYYZZ",
+      "team_id": "TXXXXXXXX",
+      "event": {
+        "type": "app_uninstalled"
+      },
+      "type": "event_callback"
+    }"#;
+
+    let expected = Event::Event { team_id: "TXXXXXXXX".into(),
+                                  event: EventPayload::AppUninstalled };
+
+    let actual = serde_json::from_str::<Event>(json).unwrap();
+
+    assert_eq!(expected, actual);
+  }
 }
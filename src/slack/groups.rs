@@ -16,23 +16,29 @@ pub trait Groups: 'static + Sync + Send + std::fmt::Debug {
 
   /// Check if a group contains a user
   fn contains_user(&self, team_id: &str, group_id: &str, user_id: &str) -> Result<bool>;
+
+  /// Forget anything remembered about a group's members, e.g. because slack told us they changed
+  fn invalidate(&self, _team_id: &str, _group_id: &str) {}
+
+  /// Hit rate of group member lookups, if they're cached
+  fn cache_stats(&self) -> Option<super::cache::CacheStats> {
+    None
+  }
 }
 
 impl Groups for super::Api {
   fn expand(&self, team_id: &str, group_id: &str) -> Result<Vec<String>> {
-    let token = self.tokens.get(team_id).ok_or(Error::NotInstalled)?;
-
-    self.client
-        .get(format!("{}/api/usergroups.users.list?usergroup={}", self.base_url, group_id))
-        .header("authorization", format!("Bearer {}", token))
-        .send()
-        .and_then(|rep| rep.error_for_status())
-        .map_err(Error::Http)
-        .and_then(|rep| rep.json::<Rep>().map_err(Error::Http))
-        .and_then(|rep| match rep.ok {
-          | true => Ok(rep.users.unwrap_or_default()),
-          | false => Err(Error::Slack(rep.error.unwrap_or_else(|| "".into()))),
-        })
+    let token = self.token(team_id)?;
+
+    super::send_retrying(|| {
+      self.client
+          .get(format!("{}/api/usergroups.users.list?usergroup={}", self.base_url, group_id))
+          .header("authorization", format!("Bearer {}", token))
+    }).and_then(|rep| rep.json::<Rep>().map_err(Error::Http))
+      .and_then(|rep| match rep.ok {
+        | true => Ok(rep.users.unwrap_or_default()),
+        | false => Err(Error::Slack(rep.error.unwrap_or_else(|| "".into()))),
+      })
   }
 
   fn contains_user(&self, team_id: &str, group_id: &str, user_id: &str) -> Result<bool> {
@@ -0,0 +1,155 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+use crate::mutex_extra::lock_discard_poison;
+
+/// How long someone has to finish installing after we send them to slack
+const MAX_AGE_SECS: i64 = 10 * 60;
+
+/// Cookie that ties an install's `state` to the browser that started it
+pub const COOKIE: &str = "mergebot_install";
+
+fn mac(secret: &str, payload: &str) -> Hmac<Sha256> {
+  let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take keys of any size");
+  mac.update(payload.as_bytes());
+  mac
+}
+
+/// A fresh nonce to start an install with,
+/// sent to the browser in the `COOKIE` cookie and to slack in the `state` parameter
+pub fn nonce() -> String {
+  nanoid::nanoid!()
+}
+
+/// `Set-Cookie` header value that gives the browser starting an install its nonce.
+///
+/// `SameSite=Lax` rather than `Strict`, since the cookie has to come back with slack's redirect.
+pub fn set_cookie(nonce: &str) -> String {
+  format!("{}={}; Path=/redirect; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+          COOKIE, nonce, MAX_AGE_SECS)
+}
+
+/// `Set-Cookie` header value that forgets the nonce once the install is finished (or failed)
+pub fn clear_cookie() -> String {
+  format!("{}=; Path=/redirect; Max-Age=0; HttpOnly; Secure; SameSite=Lax", COOKIE)
+}
+
+/// Make the `state` parameter for an install, so that we can tell
+/// when slack redirects back to us that the install started here, recently, and in the same browser.
+///
+/// Looks like `<unix timestamp>.<nonce>.<hex HMAC-SHA256 of the rest, keyed with `secret`>`.
+pub fn state(secret: &str, nonce: &str, now: DateTime<Utc>) -> String {
+  let payload = format!("{}.{}", now.timestamp(), nonce);
+  let sig = hex::encode(mac(secret, &payload).finalize().into_bytes());
+
+  format!("{}.{}", payload, sig)
+}
+
+/// Check that an install's `state` parameter is one we made with `secret` in the last 10 minutes,
+/// for the browser that sent us `nonce` in its cookie
+pub fn state_valid(secret: &str, state: &str, nonce: &str, now: DateTime<Utc>) -> bool {
+  let (payload, sig) = match state.rsplitn(2, '.').collect::<Vec<_>>()[..] {
+    | [sig, payload] => (payload, sig),
+    | _ => return false,
+  };
+
+  let (ts, state_nonce) = match payload.split_once('.') {
+    | Some(parts) => parts,
+    | None => return false,
+  };
+
+  let fresh = ts.parse::<i64>()
+                .map(|ts| (0..=MAX_AGE_SECS).contains(&(now.timestamp() - ts)))
+                .unwrap_or(false);
+
+  let same_browser = !nonce.is_empty() && state_nonce == nonce;
+
+  let authentic = hex::decode(sig).map(|sig| mac(secret, payload).verify(&sig).is_ok())
+                                  .unwrap_or(false);
+
+  fresh && same_browser && authentic
+}
+
+/// Install `state`s that have been used, so that each only works once
+#[derive(Debug, Default)]
+pub struct Used(Mutex<HashMap<String, DateTime<Utc>>>);
+
+impl Used {
+  /// Mark a state as used, yielding whether it hadn't been already
+  pub fn redeem(&self, state: &str, now: DateTime<Utc>) -> bool {
+    let mut used = lock_discard_poison(&self.0);
+
+    // a state can't be used after it expires anyway, so there's no need to remember it past then
+    used.retain(|_, at| now.signed_duration_since(*at).num_seconds() <= MAX_AGE_SECS);
+
+    // don't touch a state that's already been used, so that trying it again doesn't make us remember it longer
+    match used.contains_key(state) {
+      | true => false,
+      | false => {
+        used.insert(state.to_string(), now);
+        true
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::Duration;
+
+  use super::*;
+
+  #[test]
+  fn state_roundtrip() {
+    let now = Utc::now();
+    let state = state("shh", "abc", now);
+
+    assert!(state_valid("shh", &state, "abc", now));
+    assert!(state_valid("shh", &state, "abc", now + Duration::minutes(9)));
+
+    assert!(!state_valid("shh", &state, "abc", now + Duration::minutes(11)));
+    assert!(!state_valid("shh", &state, "abc", now - Duration::minutes(1)));
+    assert!(!state_valid("other secret", &state, "abc", now));
+  }
+
+  #[test]
+  fn state_tied_to_browser() {
+    let now = Utc::now();
+    let state = state("shh", "abc", now);
+
+    assert!(!state_valid("shh", &state, "xyz", now));
+    assert!(!state_valid("shh", &state, "", now));
+  }
+
+  #[test]
+  fn state_tampered() {
+    let now = Utc::now();
+    let state = state("shh", "abc", now);
+    let (payload, sig) = state.split_at(state.rfind('.').unwrap());
+    let (_, nonce) = payload.split_at(payload.find('.').unwrap());
+
+    let backdated = format!("{}{}{}", now.timestamp() - 60, nonce, sig);
+    let renonced = format!("{}.xyz{}", now.timestamp(), sig);
+
+    assert!(!state_valid("shh", &backdated, "abc", now));
+    assert!(!state_valid("shh", &renonced, "xyz", now));
+    assert!(!state_valid("shh", "", "abc", now));
+    assert!(!state_valid("shh", "not.a.state", "a", now));
+  }
+
+  #[test]
+  fn state_single_use() {
+    let now = Utc::now();
+    let used = Used::default();
+
+    assert!(used.redeem("a", now));
+    assert!(!used.redeem("a", now + Duration::minutes(5)));
+    assert!(used.redeem("b", now + Duration::minutes(5)));
+
+    // forgotten once it's expired anyway
+    assert!(used.redeem("a", now + Duration::minutes(11)));
+  }
+}
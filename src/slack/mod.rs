@@ -1,8 +1,23 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
 use serde::{Deserialize as De, Serialize as Ser};
 
-const SCOPES: [&str; 5] = ["bot", "chat:write:bot", "commands", "reactions:read", "usergroups:read"];
+/// How many times to send a request slack rate limits before giving up
+const MAX_ATTEMPTS: u32 = 4;
+
+/// How long to wait before the first retry of a rate limited request, if slack doesn't say
+const BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest we'll block the caller waiting to retry a rate limited request.
+/// If slack asks for longer, the caller gets `Error::RateLimited` right away.
+const MAX_WAIT: Duration = Duration::from_secs(10);
+
+/// Bot scopes we ask for when installed
+const SCOPES: [&str; 5] = ["chat:write",
+                           "commands",
+                           "files:write",
+                           "reactions:read",
+                           "usergroups:read"];
 
 /// Event models
 pub mod event;
@@ -10,15 +25,30 @@ pub mod event;
 /// Manager for access tokens
 pub mod tokens;
 
+/// Encrypting access tokens at rest
+pub mod sealed;
+
 /// OAuth Access API
 pub mod access;
 
+/// Protecting the install flow from forged redirects
+pub mod install;
+
 /// Groups API
 pub mod groups;
 
+/// Caching for the groups API
+pub mod cache;
+
 /// Sending messages
 pub mod msg;
 
+/// Responding to slash commands
+pub mod respond;
+
+/// Async variants of the slack API traits
+pub mod nonblocking;
+
 /// Slack API result
 pub type Result<T> = core::result::Result<T, self::Error>;
 
@@ -34,6 +64,12 @@ pub enum Error {
   /// Slack app not installed for team
   NotInstalled,
 
+  /// Couldn't read or write the tokens we got when installed
+  Tokens(tokens::Error),
+
+  /// Slack is rate limiting us, and asked that we wait this long before trying again
+  RateLimited(Duration),
+
   /// Some other error
   Other(String),
 }
@@ -56,17 +92,61 @@ impl Api {
            tokens,
            client }
   }
+
+  /// The bot token for a workspace we're installed in
+  fn token(&self, team_id: &str) -> Result<String> {
+    self.tokens
+        .get(team_id)
+        .map_err(Error::Tokens)
+        .and_then(|token| token.ok_or(Error::NotInstalled))
+  }
+}
+
+/// Send a request, retrying if slack rate limits it.
+///
+/// Waits as long as slack's `Retry-After` header asks, or with exponential backoff
+/// if it's missing, and gives up with `Error::RateLimited` after `MAX_ATTEMPTS`
+/// or if slack asks us to wait longer than `MAX_WAIT`.
+fn send_retrying(req: impl Fn() -> reqwest::blocking::RequestBuilder) -> Result<reqwest::blocking::Response> {
+  let mut attempt = 0;
+
+  loop {
+    let rep = req().send().map_err(Error::Http)?;
+
+    if rep.status() != http::StatusCode::TOO_MANY_REQUESTS {
+      return rep.error_for_status().map_err(Error::Http);
+    }
+
+    let wait = rep.headers()
+                  .get("retry-after")
+                  .and_then(|secs| secs.to_str().ok())
+                  .and_then(|secs| secs.parse().ok())
+                  .map(Duration::from_secs)
+                  .unwrap_or(BACKOFF * 2u32.pow(attempt));
+
+    attempt += 1;
+
+    if attempt >= MAX_ATTEMPTS || wait > MAX_WAIT {
+      return Err(Error::RateLimited(wait));
+    }
+
+    log::warn!("slack rate limited {}, retrying in {:?}", rep.url(), wait);
+    std::thread::sleep(wait);
+  }
 }
 
-/// Slack URI to redirect to as part of installation flow
-pub fn authorize_uri(client_id: impl ToString) -> String {
+/// Slack URI to redirect to as part of installation flow.
+///
+/// Slack sends `state` back to our redirect URI, see `install::state`.
+pub fn authorize_uri(client_id: impl ToString, state: impl ToString) -> String {
   let mut params: HashMap<&'static str, String> = HashMap::new();
   params.insert("client_id", client_id.to_string());
-  params.insert("scope", SCOPES.join(" "));
+  params.insert("scope", SCOPES.join(","));
+  params.insert("state", state.to_string());
 
   let params_str = serde_urlencoded::to_string(params).unwrap();
 
-  format!("https://slack.com/oauth/authorize?{}", params_str)
+  format!("https://slack.com/oauth/v2/authorize?{}", params_str)
 }
 
 /// Validate an incoming HTTP request from slack
@@ -179,12 +259,13 @@ mod tests {
 
   #[test]
   fn test_authorize_uri() {
-    let params_str = authorize_uri("FOO").strip_prefix("https://slack.com/oauth/authorize?")
-                                         .expect("URI should start with correct base url")
-                                         .to_string();
+    let params_str = authorize_uri("FOO", "BAR").strip_prefix("https://slack.com/oauth/v2/authorize?")
+                                                .expect("URI should start with correct base url")
+                                                .to_string();
     let params = serde_urlencoded::from_str::<HashMap<String, String>>(&params_str).expect("params should deserialize");
 
     assert_eq!(params.get("client_id").unwrap().as_str(), "FOO");
-    assert_eq!(params.get("scope").unwrap().as_str(), SCOPES.join(" "));
+    assert_eq!(params.get("scope").unwrap().as_str(), SCOPES.join(","));
+    assert_eq!(params.get("state").unwrap().as_str(), "BAR");
   }
 }
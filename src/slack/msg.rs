@@ -18,6 +18,14 @@ impl Id {
   pub fn equals(&self, channel: impl AsRef<str>, ts: impl AsRef<str>) -> bool {
     self.channel == channel.as_ref() && self.ts == ts.as_ref()
   }
+
+  /// Link to the message, given the domain of the slack workspace it was sent in
+  pub fn permalink(&self, team_domain: &str) -> String {
+    format!("https://{}.slack.com/archives/{}/p{}",
+            team_domain,
+            self.channel,
+            self.ts.replace('.', ""))
+  }
 }
 
 #[derive(Debug, Clone, Ser, De)]
@@ -28,6 +36,14 @@ struct RepRaw {
   ts: Option<String>,
 }
 
+#[derive(Debug, Clone, Ser, De)]
+struct UploadUrlRaw {
+  ok: bool,
+  error: Option<String>,
+  upload_url: Option<String>,
+  file_id: Option<String>,
+}
+
 /// Send message OK response
 #[derive(Debug, PartialEq, Clone, Ser, De)]
 pub struct Rep {
@@ -59,6 +75,12 @@ pub trait Messages: 'static + Sync + Send + std::fmt::Debug {
 
   /// Send a message in a thread
   fn send_thread(&self, team_id: &str, thread_parent: &Id, blocks: &[Block]) -> Result<Rep>;
+
+  /// Replace the content of a message we sent
+  fn update(&self, team_id: &str, id: &Id, blocks: &[Block]) -> Result<Rep>;
+
+  /// Attach a plain text snippet to a thread, for text too long for a message
+  fn upload_snippet(&self, team_id: &str, thread_parent: &Id, title: &str, content: &str) -> Result<()>;
 }
 
 fn send_body(channel: Option<&str>,
@@ -82,28 +104,39 @@ fn send_body(channel: Option<&str>,
 }
 
 fn send_base(base_url: &str,
-             token: Option<String>,
+             token: Result<String>,
              client: &reqwest::blocking::Client,
              channel_id: Option<&str>,
              thread_parent: Option<&Id>,
              blocks: &[Block])
              -> Result<Rep> {
-  let token = token.ok_or(Error::NotInstalled)?;
-
-  client.post(format!("{}/api/chat.postMessage", base_url))
-        .json(&send_body(channel_id, blocks, thread_parent))
-        .header("authorization", format!("Bearer {}", token))
-        .send()
-        .and_then(|rep| rep.error_for_status())
-        .and_then(|rep| rep.json::<RepRaw>())
-        .map_err(Error::Http)
-        .and_then(Rep::try_from_raw)
+  let token = token?;
+
+  let body = send_body(channel_id, blocks, thread_parent);
+
+  super::send_retrying(|| {
+    client.post(format!("{}/api/chat.postMessage", base_url))
+          .json(&body)
+          .header("authorization", format!("Bearer {}", token))
+  }).and_then(|rep| rep.json::<RepRaw>().map_err(Error::Http))
+    .and_then(Rep::try_from_raw)
+}
+
+fn update_body(id: &Id, blocks: &[Block]) -> serde_json::value::Map<String, serde_json::Value> {
+  let mut map = serde_json::Map::new();
+  let blocks = serde_json::to_value(blocks).expect("blocks should serialize");
+
+  map.insert("channel".into(), id.channel.as_str().into());
+  map.insert("ts".into(), id.ts.as_str().into());
+  map.insert("blocks".into(), blocks);
+
+  map
 }
 
 impl Messages for Api {
   fn send(&self, team_id: &str, channel_id: &str, blocks: &[Block]) -> Result<Rep> {
     send_base(&self.base_url,
-              self.tokens.get(team_id),
+              self.token(team_id),
               self.client,
               Some(channel_id),
               None,
@@ -112,10 +145,80 @@ impl Messages for Api {
 
   fn send_thread(&self, team_id: &str, thread_parent: &Id, blocks: &[Block]) -> Result<Rep> {
     send_base(&self.base_url,
-              self.tokens.get(team_id),
+              self.token(team_id),
               self.client,
               None,
               Some(thread_parent),
               blocks)
   }
+
+  fn update(&self, team_id: &str, id: &Id, blocks: &[Block]) -> Result<Rep> {
+    let token = self.token(team_id)?;
+    let body = update_body(id, blocks);
+
+    super::send_retrying(|| {
+      self.client
+          .post(format!("{}/api/chat.update", self.base_url))
+          .json(&body)
+          .header("authorization", format!("Bearer {}", token))
+    }).and_then(|rep| rep.json::<RepRaw>().map_err(Error::Http))
+      .and_then(Rep::try_from_raw)
+  }
+
+  fn upload_snippet(&self, team_id: &str, thread_parent: &Id, title: &str, content: &str) -> Result<()> {
+    let token = self.token(team_id)?;
+    let auth = format!("Bearer {}", token);
+    let slack_ok = |ok: bool, error: Option<String>| match ok {
+      | true => Ok(()),
+      | false => Err(Error::Slack(error.unwrap_or_else(|| "no error".into()))),
+    };
+
+    // ask slack where to put the file,
+    let length = content.len().to_string();
+    let form = [("filename", format!("{}.txt", title)), ("length", length)];
+
+    let upload = super::send_retrying(|| {
+                   self.client
+                       .post(format!("{}/api/files.getUploadURLExternal", self.base_url))
+                       .form(&form)
+                       .header("authorization", &auth)
+                 }).and_then(|rep| rep.json::<UploadUrlRaw>().map_err(Error::Http))
+                   .and_then(|raw| slack_ok(raw.ok, raw.error.clone()).map(|_| raw))?;
+
+    let (upload_url, file_id) = match (upload.upload_url, upload.file_id) {
+      | (Some(url), Some(id)) => (url, id),
+      | _ => return Err(Error::Other("files.getUploadURLExternal didn't say where to upload".into())),
+    };
+
+    super::send_retrying(|| self.client.post(&upload_url).body(content.to_string()))?;
+
+    // then share it in the thread once it's there
+    let body = serde_json::json!({
+      "files": [{"id": file_id, "title": title}],
+      "channel_id": thread_parent.channel,
+      "thread_ts": thread_parent.ts,
+    });
+
+    super::send_retrying(|| {
+      self.client
+          .post(format!("{}/api/files.completeUploadExternal", self.base_url))
+          .json(&body)
+          .header("authorization", &auth)
+    }).and_then(|rep| rep.json::<RepRaw>().map_err(Error::Http))
+      .and_then(|raw| slack_ok(raw.ok, raw.error))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn permalink() {
+    let id = Id { channel: "C0001".into(),
+                  ts: "1603123456.000200".into() };
+
+    assert_eq!(id.permalink("example"),
+               "https://example.slack.com/archives/C0001/p1603123456000200");
+  }
 }
@@ -0,0 +1,47 @@
+use std::{future::Future, pin::Pin};
+
+use super::{access::{Access, AccessRep},
+            groups::Groups,
+            Error,
+            Result};
+
+/// A boxed future yielding a slack API result
+pub type Fut<T> = Pin<Box<dyn Future<Output = Result<T>> + Send>>;
+
+/// Run a blocking slack API call on tokio's blocking thread pool,
+/// so that awaiting it doesn't stall the async worker thread it was awaited on.
+fn unblock<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Fut<T> {
+  Box::pin(async move {
+    tokio::task::spawn_blocking(f).await
+                                  .map_err(|e| Error::Other(format!("slack API call panicked: {:?}", e)))
+                                  .and_then(|res| res)
+  })
+}
+
+/// Async variant of `Groups`, usable from request handlers
+pub trait GroupsAsync {
+  /// Check if a group contains a user
+  fn contains_user_async(&'static self, team_id: &str, group_id: &str, user_id: &str) -> Fut<bool>;
+}
+
+impl<T: Groups + ?Sized> GroupsAsync for T {
+  fn contains_user_async(&'static self, team_id: &str, group_id: &str, user_id: &str) -> Fut<bool> {
+    let (team_id, group_id, user_id) = (team_id.to_string(), group_id.to_string(), user_id.to_string());
+
+    unblock(move || self.contains_user(&team_id, &group_id, &user_id))
+  }
+}
+
+/// Async variant of `Access`, usable from request handlers
+pub trait AccessAsync {
+  /// Exchange an OAuth code for an access token
+  fn access_async(&'static self, code: &str, client_id: &str, client_secret: &str) -> Fut<AccessRep>;
+}
+
+impl<T: Access + ?Sized> AccessAsync for T {
+  fn access_async(&'static self, code: &str, client_id: &str, client_secret: &str) -> Fut<AccessRep> {
+    let (code, client_id, client_secret) = (code.to_string(), client_id.to_string(), client_secret.to_string());
+
+    unblock(move || self.access(&code, &client_id, &client_secret))
+  }
+}
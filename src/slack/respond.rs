@@ -0,0 +1,101 @@
+use serde::{Deserialize as De, Serialize as Ser};
+use slack_blocks::Block;
+
+use super::{Api, Error, Result};
+
+/// Slack won't display section text longer than this
+const MAX_SECTION_LEN: usize = 3000;
+
+/// Who can see a response to a slash command
+#[derive(Ser, De, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+  /// Only the user who sent the command
+  Ephemeral,
+  /// Everyone in the channel the command was sent in
+  InChannel,
+}
+
+/// A response to a slash command
+#[derive(Ser, Debug)]
+pub struct Response {
+  /// Who can see the response
+  #[serde(rename = "response_type")]
+  pub visibility: Visibility,
+  /// Plain text fallback, used in notifications
+  pub text: String,
+  /// Message content
+  pub blocks: Vec<Block<'static>>,
+}
+
+impl Response {
+  /// A response only the user who sent the command can see
+  pub fn ephemeral(text: impl ToString) -> Self {
+    Self::new(Visibility::Ephemeral, text.to_string())
+  }
+
+  /// A response everyone in the channel can see
+  pub fn in_channel(text: impl ToString) -> Self {
+    Self::new(Visibility::InChannel, text.to_string())
+  }
+
+  fn new(visibility: Visibility, text: String) -> Self {
+    // split long text (e.g. `/deploy list`) into as few sections as slack will allow
+    let mut sections: Vec<String> = vec![];
+
+    for line in text.lines() {
+      match sections.last_mut() {
+        | Some(section) if section.len() + line.len() < MAX_SECTION_LEN => {
+          section.push('\n');
+          section.push_str(line);
+        },
+        | _ => sections.push(line.to_string()),
+      }
+    }
+
+    let blocks = sections.into_iter()
+                         .map(|section| -> Block {
+                           use slack_blocks::blox::*;
+                           blox! {<section_block><text kind=mrkdwn>{section}</text></section_block>}.into()
+                         })
+                         .collect();
+
+    Self { visibility,
+           text,
+           blocks }
+  }
+}
+
+/// Respond to slash commands
+pub trait Respond: 'static + Sync + Send + std::fmt::Debug {
+  /// Post a response to a slash command's `response_url`.
+  ///
+  /// Slack accepts up to 5 responses to a command within 30 minutes of it being sent.
+  fn respond(&self, response_url: &str, response: &Response) -> Result<()>;
+}
+
+impl Respond for Api {
+  fn respond(&self, response_url: &str, response: &Response) -> Result<()> {
+    self.client
+        .post(response_url)
+        .json(response)
+        .send()
+        .and_then(|rep| rep.error_for_status())
+        .map(|_| ())
+        .map_err(Error::Http)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn response_splits_long_text() {
+    let line = "a".repeat(2000);
+    let rep = Response::ephemeral(format!("{}\n{}\nshort", line, line));
+
+    assert_eq!(rep.blocks.len(), 2);
+    assert_eq!(rep.visibility, Visibility::Ephemeral);
+  }
+}
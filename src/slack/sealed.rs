@@ -0,0 +1,355 @@
+use chacha20poly1305::{aead::{Aead, NewAead, Payload},
+                       XChaCha20Poly1305,
+                       XNonce};
+
+use super::{access::AccessRep, tokens, tokens::TokenMgr};
+
+/// Marks access tokens we've encrypted, so that we can tell them apart from plaintext ones
+const SEALED_PREFIX: &str = "sealed:";
+
+const NONCE_LEN: usize = 24;
+
+/// Errors encounterable opening a store of sealed tokens
+#[derive(Debug)]
+pub enum Error {
+  /// Couldn't read or write the store being wrapped
+  Store(tokens::Error),
+
+  /// Encryption is required, but these teams' tokens are stored in plaintext
+  Unencrypted(Vec<String>),
+
+  /// These teams' tokens weren't sealed with any key we were given
+  UnknownKey(Vec<String>),
+
+  /// These teams' tokens are sealed, but we weren't given a key
+  NoKey(Vec<String>),
+}
+
+/// Make sure a store we aren't given a key for doesn't have sealed tokens in it,
+/// which we'd otherwise send to slack as if they were tokens.
+pub fn check_unsealed(store: &dyn TokenMgr) -> Result<(), Error> {
+  let sealed = store.tokens()
+                    .map_err(Error::Store)?
+                    .into_iter()
+                    .filter(|rep| rep.access_token.starts_with(SEALED_PREFIX))
+                    .map(|rep| rep.team.id)
+                    .collect::<Vec<_>>();
+
+  match sealed.is_empty() {
+    | true => Ok(()),
+    | false => Err(Error::NoKey(sealed)),
+  }
+}
+
+/// A 256-bit key to seal tokens with
+#[derive(Clone, Copy)]
+pub struct Key(chacha20poly1305::Key);
+
+// keep the key out of logs
+impl std::fmt::Debug for Key {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str("Key(..)")
+  }
+}
+
+impl Key {
+  /// Parse a base64-encoded key, e.g. from `openssl rand -base64 32`
+  pub fn from_base64(b64: &str) -> Option<Self> {
+    base64::decode(b64.trim()).ok()
+                              .filter(|bytes| bytes.len() == 32)
+                              .map(|bytes| Self(*chacha20poly1305::Key::from_slice(&bytes)))
+  }
+
+  fn cipher(&self) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new(&self.0)
+  }
+}
+
+enum Opened {
+  Plaintext,
+  Current(String),
+  Old(String),
+  /// Sealed before tokens were bound to their team
+  Unbound(String),
+  UnknownKey,
+}
+
+/// Token store decorator that encrypts access tokens (XChaCha20-Poly1305) before they reach the store it wraps.
+///
+/// Each token is bound to its team's id, so that a sealed token copied into another team's entry won't open.
+///
+/// Tokens sealed with one of `old_keys` are still readable, and are re-sealed with `key` when the store is opened,
+/// so rotating keys is a matter of moving the current key to `old_keys` and restarting.
+#[derive(Debug)]
+pub struct Sealed {
+  inner: Box<dyn TokenMgr>,
+  key: Key,
+  old_keys: Vec<Key>,
+}
+
+impl Sealed {
+  /// Wrap a token store, sealing tokens already in it that are plaintext or sealed with an old key.
+  ///
+  /// If `required`, plaintext tokens in the store are an error rather than being sealed.
+  pub fn open(inner: Box<dyn TokenMgr>, key: Key, old_keys: Vec<Key>, required: bool) -> Result<Self, Error> {
+    let sealed = Self { inner, key, old_keys };
+
+    let mut reps = sealed.inner.tokens().map_err(Error::Store)?;
+    let mut plaintext = vec![];
+    let mut unknown_key = vec![];
+    let mut stale = false;
+
+    for rep in reps.iter_mut() {
+      match sealed.open_token(&rep.team.id, &rep.access_token) {
+        | Opened::Plaintext => plaintext.push(rep.team.id.clone()),
+        | Opened::Current(token) => rep.access_token = token,
+        | Opened::Old(token) | Opened::Unbound(token) => {
+          rep.access_token = token;
+          stale = true;
+        },
+        | Opened::UnknownKey => unknown_key.push(rep.team.id.clone()),
+      }
+    }
+
+    if required && !plaintext.is_empty() {
+      return Err(Error::Unencrypted(plaintext));
+    }
+
+    if !unknown_key.is_empty() {
+      return Err(Error::UnknownKey(unknown_key));
+    }
+
+    if stale || !plaintext.is_empty() {
+      log::info!("(tokens) sealing {} tokens with the current key", reps.len());
+      sealed.set_tokens(reps).map_err(Error::Store)?;
+    }
+
+    Ok(sealed)
+  }
+
+  fn seal_token(&self, team_id: &str, token: &str) -> String {
+    let nonce = rand::random::<[u8; NONCE_LEN]>();
+    let payload = Payload { msg: token.as_bytes(),
+                            aad: team_id.as_bytes() };
+    let sealed = self.key
+                     .cipher()
+                     .encrypt(XNonce::from_slice(&nonce), payload)
+                     .expect("encrypt token");
+
+    let mut bytes = nonce.to_vec();
+    bytes.extend(sealed);
+
+    format!("{}{}", SEALED_PREFIX, base64::encode(bytes))
+  }
+
+  fn open_token(&self, team_id: &str, token: &str) -> Opened {
+    let bytes = match token.strip_prefix(SEALED_PREFIX) {
+      | Some(b64) => base64::decode(b64).ok().filter(|bytes| bytes.len() > NONCE_LEN),
+      | None => return Opened::Plaintext,
+    };
+
+    let bytes = match bytes {
+      | Some(bytes) => bytes,
+      | None => return Opened::UnknownKey,
+    };
+
+    let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+    let open_with = |key: &Key, aad: &[u8]| {
+      key.cipher()
+         .decrypt(XNonce::from_slice(nonce), Payload { msg: sealed, aad })
+         .ok()
+         .and_then(|token| String::from_utf8(token).ok())
+    };
+    let open = |key: &Key| open_with(key, team_id.as_bytes());
+
+    // tokens sealed before they were bound to their team are only accepted by `Sealed::open`,
+    // which seals them again (bound this time)
+    let unbound = |key: &Key| open_with(key, &[]);
+
+    open(&self.key).map(Opened::Current)
+                   .or_else(|| self.old_keys.iter().find_map(open).map(Opened::Old))
+                   .or_else(|| {
+                     std::iter::once(&self.key).chain(&self.old_keys)
+                                               .find_map(unbound)
+                                               .map(Opened::Unbound)
+                   })
+                   .unwrap_or(Opened::UnknownKey)
+  }
+}
+
+impl TokenMgr for Sealed {
+  // fails if any token can't be opened, rather than leaving it out
+  // and having it deleted when the rest are written back
+  fn tokens(&self) -> tokens::Result<Vec<AccessRep>> {
+    let mut reps = self.inner.tokens()?;
+    let mut unknown_key = vec![];
+
+    for rep in reps.iter_mut() {
+      match self.open_token(&rep.team.id, &rep.access_token) {
+        | Opened::Plaintext => (),
+        | Opened::Current(token) | Opened::Old(token) => rep.access_token = token,
+        | Opened::Unbound(_) | Opened::UnknownKey => unknown_key.push(rep.team.id.clone()),
+      }
+    }
+
+    match unknown_key.is_empty() {
+      | true => Ok(reps),
+      | false => Err(tokens::Error::Sealed(unknown_key)),
+    }
+  }
+
+  fn set_tokens(&self, reps: Vec<AccessRep>) -> tokens::Result<()> {
+    let reps = reps.into_iter()
+                   .map(|rep| AccessRep { access_token: self.seal_token(&rep.team.id, &rep.access_token),
+                                          ..rep })
+                   .collect();
+
+    self.inner.set_tokens(reps)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+
+  use super::*;
+  use crate::{mutex_extra::lock_discard_poison, slack::access::Team};
+
+  fn rep(team_id: &str, token: &str) -> AccessRep {
+    AccessRep { access_token: token.into(),
+                scope: String::new(),
+                bot_user_id: String::new(),
+                team: Team { id: team_id.into(),
+                             name: String::new() } }
+  }
+
+  /// In-memory store that can be shared between the `Sealed` under test and the test itself
+  #[derive(Debug, Clone)]
+  struct Mem(Arc<Mutex<Vec<AccessRep>>>);
+
+  impl TokenMgr for Mem {
+    fn tokens(&self) -> tokens::Result<Vec<AccessRep>> {
+      Ok(lock_discard_poison(&self.0).clone())
+    }
+
+    fn set_tokens(&self, reps: Vec<AccessRep>) -> tokens::Result<()> {
+      *lock_discard_poison(&self.0) = reps;
+      Ok(())
+    }
+  }
+
+  fn store(reps: Vec<AccessRep>) -> Mem {
+    Mem(Arc::new(Mutex::new(reps)))
+  }
+
+  fn key(byte: u8) -> Key {
+    Key::from_base64(&base64::encode([byte; 32])).unwrap()
+  }
+
+  #[test]
+  fn key_from_base64() {
+    assert!(Key::from_base64(&base64::encode([0u8; 32])).is_some());
+    assert!(Key::from_base64(&base64::encode([0u8; 16])).is_none());
+    assert!(Key::from_base64("not base64!").is_none());
+  }
+
+  #[test]
+  fn seals_at_rest() {
+    let inner = store(vec![]);
+    let sealed = Sealed::open(Box::from(inner.clone()), key(1), vec![], true).unwrap();
+
+    sealed.register(&rep("T1", "xoxb-1")).unwrap();
+
+    let at_rest = inner.tokens().unwrap()[0].access_token.clone();
+    assert!(at_rest.starts_with(SEALED_PREFIX));
+    assert!(!at_rest.contains("xoxb-1"));
+
+    assert_eq!(sealed.get("T1").unwrap(), Some("xoxb-1".into()));
+  }
+
+  #[test]
+  fn bound_to_team() {
+    let inner = store(vec![]);
+    let sealed = Sealed::open(Box::from(inner.clone()), key(1), vec![], true).unwrap();
+    sealed.register(&rep("T1", "xoxb-1")).unwrap();
+
+    // T1's sealed token, passed off as T2's
+    let stolen = inner.tokens().unwrap()[0].access_token.clone();
+    inner.register(&rep("T2", &stolen)).unwrap();
+
+    assert!(matches!(sealed.tokens(), Err(tokens::Error::Sealed(teams)) if teams == vec!["T2"]));
+  }
+
+  #[test]
+  fn reseals_unbound_tokens() {
+    let nonce = [0u8; NONCE_LEN];
+    let unbound = key(1).cipher()
+                        .encrypt(XNonce::from_slice(&nonce), &b"xoxb-1"[..])
+                        .unwrap();
+    let unbound = format!("{}{}", SEALED_PREFIX, base64::encode([&nonce[..], &unbound].concat()));
+
+    let inner = store(vec![rep("T1", &unbound)]);
+    let sealed = Sealed::open(Box::from(inner.clone()), key(1), vec![], true).unwrap();
+
+    assert_eq!(sealed.get("T1").unwrap(), Some("xoxb-1".into()));
+    assert_ne!(inner.get("T1").unwrap(), Some(unbound.clone()));
+
+    // but only when the store is opened
+    inner.register(&rep("T2", &unbound)).unwrap();
+    assert!(matches!(sealed.tokens(), Err(tokens::Error::Sealed(teams)) if teams == vec!["T2"]));
+  }
+
+  #[test]
+  fn seals_plaintext_unless_required() {
+    let inner = store(vec![rep("T1", "xoxb-1")]);
+
+    assert!(matches!(Sealed::open(Box::from(inner.clone()), key(1), vec![], true),
+                     Err(Error::Unencrypted(teams)) if teams == vec!["T1"]));
+    assert_eq!(inner.get("T1").unwrap(), Some("xoxb-1".into()));
+
+    let sealed = Sealed::open(Box::from(inner.clone()), key(1), vec![], false).unwrap();
+
+    assert_ne!(inner.get("T1").unwrap(), Some("xoxb-1".into()));
+    assert_eq!(sealed.get("T1").unwrap(), Some("xoxb-1".into()));
+  }
+
+  #[test]
+  fn rotates_keys() {
+    let inner = store(vec![]);
+    let sealed = Sealed::open(Box::from(inner.clone()), key(1), vec![], true).unwrap();
+    sealed.register(&rep("T1", "xoxb-1")).unwrap();
+
+    assert!(matches!(Sealed::open(Box::from(inner.clone()), key(2), vec![], true),
+                     Err(Error::UnknownKey(teams)) if teams == vec!["T1"]));
+
+    Sealed::open(Box::from(inner.clone()), key(2), vec![key(1)], true).unwrap();
+
+    let sealed = Sealed::open(Box::from(inner), key(2), vec![], true).unwrap();
+    assert_eq!(sealed.get("T1").unwrap(), Some("xoxb-1".into()));
+  }
+
+  #[test]
+  fn unopenable_tokens_arent_dropped() {
+    let inner = store(vec![]);
+    let sealed = Sealed::open(Box::from(inner.clone()), key(2), vec![], true).unwrap();
+
+    // sealed with a key this store wasn't opened with, e.g. by another instance mid-rotation
+    let other = Sealed::open(Box::from(inner.clone()), key(1), vec![], true).unwrap();
+    other.register(&rep("T1", "xoxb-1")).unwrap();
+
+    assert!(matches!(sealed.tokens(), Err(tokens::Error::Sealed(teams)) if teams == vec!["T1"]));
+    assert!(sealed.register(&rep("T2", "xoxb-2")).is_err());
+    assert_eq!(inner.tokens().unwrap().len(), 1);
+  }
+
+  #[test]
+  fn sealed_without_key() {
+    let inner = store(vec![rep("T1", "xoxb-1")]);
+    assert!(check_unsealed(&inner).is_ok());
+
+    let sealed = Sealed::open(Box::from(inner.clone()), key(1), vec![], false).unwrap();
+    sealed.register(&rep("T2", "xoxb-2")).unwrap();
+
+    assert!(matches!(check_unsealed(&inner), Err(Error::NoKey(teams)) if teams == vec!["T1", "T2"]));
+  }
+}
@@ -1,42 +1,238 @@
-use std::fs;
+use std::{fmt, fs, io, sync::Mutex};
 
 use super::access::AccessRep;
+use crate::mutex_extra::lock_discard_poison;
 
+/// Token store result
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Errors encounterable reading or writing stored tokens
+#[derive(Debug)]
+pub enum Error {
+  /// Error reading or writing the tokens file
+  Io(io::Error),
+  /// The tokens file isn't a list of tokens
+  Json(serde_json::Error),
+  /// These teams' tokens are sealed with a key we don't have
+  Sealed(Vec<String>),
+}
+
+/// Bot tokens for each workspace mergebot is installed in.
+///
+/// If the stored tokens can't be read, nothing is written,
+/// so that a store we misread isn't replaced with what little we understood of it.
 pub trait TokenMgr: 'static + std::fmt::Debug + Sync + Send {
-  fn tokens(&self) -> Vec<AccessRep>;
-  fn set_tokens(&self, reps: Vec<AccessRep>);
+  /// Every workspace's token
+  fn tokens(&self) -> Result<Vec<AccessRep>>;
+
+  /// Replace every workspace's token
+  fn set_tokens(&self, reps: Vec<AccessRep>) -> Result<()>;
+
+  /// Save a workspace's token, replacing the one from any previous install
+  fn register(&self, rep: &AccessRep) -> Result<()> {
+    let mut reps = self.tokens()?;
+    upsert(&mut reps, rep);
+
+    self.set_tokens(reps)
+  }
+
+  /// Forget a workspace's token, yielding it if there was one
+  fn revoke(&self, team_id: &str) -> Result<Option<AccessRep>> {
+    let mut reps = self.tokens()?;
+    let removed = remove(&mut reps, team_id);
 
-  fn register(&self, rep: &AccessRep) {
-    let mut reps = self.tokens();
-    reps.push(rep.clone());
+    if removed.is_some() {
+      self.set_tokens(reps)?;
+    }
 
-    self.set_tokens(reps);
+    Ok(removed)
   }
 
-  fn get(&self, team_id: &str) -> Option<String> {
+  /// The token we got when installed to a workspace
+  fn rep(&self, team_id: &str) -> Result<Option<AccessRep>> {
     self.tokens()
-        .into_iter()
-        .find(|rep| rep.team.id == team_id.as_ref())
-        .map(|rep| rep.access_token)
+        .map(|reps| reps.into_iter().find(|rep| rep.team.id == team_id))
   }
+
+  /// The access token for a workspace
+  fn get(&self, team_id: &str) -> Result<Option<String>> {
+    self.rep(team_id).map(|rep| rep.map(|rep| rep.access_token))
+  }
+}
+
+fn upsert(reps: &mut Vec<AccessRep>, rep: &AccessRep) {
+  reps.retain(|r| r.team.id != rep.team.id);
+  reps.push(rep.clone());
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct Fs;
-type File = Vec<AccessRep>;
+fn remove(reps: &mut Vec<AccessRep>, team_id: &str) -> Option<AccessRep> {
+  let ix = reps.iter().position(|rep| rep.team.id == team_id)?;
+  Some(reps.remove(ix))
+}
+
+/// Tokens stored in a JSON file, which is replaced whole on every change
+/// so that a crash mid-write can't lose every workspace's token
+#[derive(Debug, Clone)]
+pub struct Fs {
+  path: String,
+}
 
-fn read_file() -> File {
-  let exist = fs::read_to_string("./access_reps.json").unwrap();
-  serde_json::from_str(&exist).unwrap()
+impl Fs {
+  /// Store tokens in the file at `path`, which is created on the first install
+  pub fn new(path: impl ToString) -> Self {
+    Self { path: path.to_string() }
+  }
 }
 
 impl TokenMgr for Fs {
-  fn tokens(&self) -> Vec<AccessRep> {
-    read_file()
+  fn tokens(&self) -> Result<Vec<AccessRep>> {
+    match fs::read_to_string(&self.path) {
+      | Ok(json) => serde_json::from_str(&json).map_err(Error::Json),
+      | Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+      | Err(e) => Err(Error::Io(e)),
+    }
+  }
+
+  fn set_tokens(&self, reps: Vec<AccessRep>) -> Result<()> {
+    let json = serde_json::to_string_pretty(&reps).map_err(Error::Json)?;
+
+    crate::extra::write_atomic(&self.path, json).map_err(Error::Io)
+  }
+}
+
+/// Token store decorator that keeps tokens in memory,
+/// so that sending a message doesn't read the store every time.
+///
+/// Tokens are read from the store the first time they're needed,
+/// and changes are written through to it.
+pub struct Cached {
+  inner: Box<dyn TokenMgr>,
+  reps: Mutex<Option<Vec<AccessRep>>>,
+}
+
+// keep tokens out of logs
+impl fmt::Debug for Cached {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Cached").field("inner", &self.inner).finish()
+  }
+}
+
+impl Cached {
+  /// Wrap a token store, keeping its tokens in memory
+  pub fn new(inner: Box<dyn TokenMgr>) -> Self {
+    Self { inner,
+           reps: Mutex::new(None) }
+  }
+
+  fn load(&self, cached: &mut Option<Vec<AccessRep>>) -> Result<Vec<AccessRep>> {
+    match cached {
+      | Some(reps) => Ok(reps.clone()),
+      | None => self.inner.tokens().map(|reps| cached.get_or_insert(reps).clone()),
+    }
+  }
+
+  // holds the lock throughout so that concurrent installs don't lose each other's tokens,
+  // and only changes the cache once the store has been written to
+  fn modify<R>(&self, f: impl FnOnce(&mut Vec<AccessRep>) -> R) -> Result<R> {
+    let mut cached = lock_discard_poison(&self.reps);
+    let mut reps = self.load(&mut cached)?;
+    let out = f(&mut reps);
+
+    self.inner.set_tokens(reps.clone())?;
+    *cached = Some(reps);
+    Ok(out)
   }
+}
+
+impl TokenMgr for Cached {
+  fn tokens(&self) -> Result<Vec<AccessRep>> {
+    self.load(&mut lock_discard_poison(&self.reps))
+  }
+
+  fn set_tokens(&self, reps: Vec<AccessRep>) -> Result<()> {
+    self.modify(|cached| *cached = reps)
+  }
+
+  fn register(&self, rep: &AccessRep) -> Result<()> {
+    self.modify(|reps| upsert(reps, rep))
+  }
+
+  fn revoke(&self, team_id: &str) -> Result<Option<AccessRep>> {
+    self.modify(|reps| remove(reps, team_id))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::slack::access::Team;
+
+  fn rep(team_id: &str, token: &str) -> AccessRep {
+    AccessRep { access_token: token.into(),
+                scope: String::new(),
+                bot_user_id: String::new(),
+                team: Team { id: team_id.into(),
+                             name: String::new() } }
+  }
+
+  #[derive(Debug, Default)]
+  struct Mem(Mutex<Vec<AccessRep>>);
+
+  impl TokenMgr for Mem {
+    fn tokens(&self) -> Result<Vec<AccessRep>> {
+      Ok(lock_discard_poison(&self.0).clone())
+    }
+
+    fn set_tokens(&self, reps: Vec<AccessRep>) -> Result<()> {
+      *lock_discard_poison(&self.0) = reps;
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn register_replaces_reinstalls() {
+    let tokens = Mem::default();
+
+    tokens.register(&rep("T1", "xoxb-1")).unwrap();
+    tokens.register(&rep("T2", "xoxb-2")).unwrap();
+    tokens.register(&rep("T1", "xoxb-3")).unwrap();
+
+    assert_eq!(tokens.tokens().unwrap().len(), 2);
+    assert_eq!(tokens.get("T1").unwrap(), Some("xoxb-3".into()));
+    assert_eq!(tokens.get("T2").unwrap(), Some("xoxb-2".into()));
+  }
+
+  #[test]
+  fn revoke() {
+    let tokens = Cached::new(Box::from(Mem::default()));
+
+    tokens.register(&rep("T1", "xoxb-1")).unwrap();
+
+    assert_eq!(tokens.revoke("T1").unwrap(), Some(rep("T1", "xoxb-1")));
+    assert_eq!(tokens.revoke("T1").unwrap(), None);
+    assert_eq!(tokens.get("T1").unwrap(), None);
+    assert!(tokens.inner.tokens().unwrap().is_empty());
+  }
+
+  #[test]
+  fn fs_missing_file() {
+    let tokens = Fs::new("./this-file-does-not-exist.json");
+
+    assert!(tokens.tokens().unwrap().is_empty());
+    assert_eq!(tokens.get("T1").unwrap(), None);
+  }
+
+  #[test]
+  fn fs_unreadable_file_isnt_overwritten() {
+    let path = std::env::temp_dir().join(format!("mergebot-tokens-{}.json", nanoid::nanoid!()));
+    fs::write(&path, "not json").unwrap();
+
+    let tokens = Cached::new(Box::from(Fs::new(path.display())));
+
+    assert!(matches!(tokens.tokens(), Err(Error::Json(_))));
+    assert!(matches!(tokens.register(&rep("T1", "xoxb-1")), Err(Error::Json(_))));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "not json");
 
-  fn set_tokens(&self, reps: Vec<AccessRep>) {
-    let new = serde_json::to_string_pretty(&reps).unwrap();
-    fs::write("./access_reps.json", new).unwrap();
+    fs::remove_file(&path).unwrap();
   }
 }
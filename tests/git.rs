@@ -147,11 +147,16 @@ fn test_merge(state: &State, repo: &Box<dyn git::RepoContext>) {
   let tip_qa = change_qa(&state, &repo);
 
   repo.switch(&staging).unwrap();
+  let prev_staging = repo.sha(&staging).unwrap();
   repo.merge(&qa).unwrap();
 
   let tip_staging = state.git_tip_head();
 
   assert_eq!(tip_qa, tip_staging);
+
+  let log = repo.log(&prev_staging, &repo.sha(&staging).unwrap()).unwrap();
+
+  assert!(log.iter().any(|c| c.subject == "create foo.txt"));
 }
 
 /// Test that pushing to upstreams succeed
@@ -162,6 +167,8 @@ fn test_push(repo: &Box<dyn git::RepoContext>) {
   repo.switch(&staging).unwrap();
   repo.push().unwrap();
 
+  assert_eq!(repo.last_pushed(&staging).unwrap(), Some(repo.sha(&staging).unwrap()));
+
   repo.switch(&qa).unwrap();
   repo.push().unwrap();
 }
@@ -0,0 +1,95 @@
+use mergebot::mattermost::{self,
+                           posts::{Button, Post, Posts},
+                           ActionKind};
+use mockito::{mock, Matcher as Match};
+use reqwest::blocking::Client;
+
+fn pretend_static<T>(t: &T) -> &'static T {
+  unsafe { std::mem::transmute::<&T, &'static T>(t) }
+}
+
+fn mk_api(client: &'static Client) -> mattermost::Api {
+  mattermost::Api::new(mockito::server_url(),
+                       "bot_token",
+                       "https://mergebot.example.com/api/v1/mattermost/action",
+                       "shh",
+                       client)
+}
+
+#[test]
+pub fn posts_create() {
+  let body_expected = serde_json::json!({
+    "channel_id": "C1234",
+    "root_id": "",
+    "message": "hello",
+    "props": {
+      "attachments": [{
+        "actions": [{
+          "id": "approve",
+          "name": "Approve",
+          "integration": {
+            "url": "https://mergebot.example.com/api/v1/mattermost/action",
+            "context": {"action": "approve", "job_id": "J1234", "secret": "shh"}
+          }
+        }]
+      }]
+    }
+  });
+
+  let rep = serde_json::json!({
+    "id": "P1234",
+    "channel_id": "C1234",
+    "message": "hello"
+  });
+
+  let moq = mock("POST", "/api/v4/posts").match_header("authorization", Match::Exact("Bearer bot_token".into()))
+                                         .match_body(Match::Json(body_expected))
+                                         .with_status(201)
+                                         .with_header("Content-Type", "application/json")
+                                         .with_body(serde_json::to_string(&rep).unwrap())
+                                         .create();
+
+  let client = Client::new();
+  let api = mk_api(pretend_static(&client));
+
+  let button = Button { label: "Approve",
+                        action: ActionKind::Approve,
+                        job_id: "J1234".into() };
+
+  let res = api.create("C1234", None, "hello", &[button]);
+
+  moq.assert();
+
+  assert_eq!(res.unwrap(),
+             Post { id: "P1234".into(),
+                    channel_id: "C1234".into() })
+}
+
+#[test]
+pub fn posts_patch() {
+  let body_expected = serde_json::json!({
+    "message": "updated",
+    "props": {"attachments": []}
+  });
+
+  let rep = serde_json::json!({
+    "id": "P5678",
+    "channel_id": "C1234",
+    "message": "updated"
+  });
+
+  let moq = mock("PUT", "/api/v4/posts/P5678/patch").match_body(Match::Json(body_expected))
+                                                    .with_status(200)
+                                                    .with_header("Content-Type", "application/json")
+                                                    .with_body(serde_json::to_string(&rep).unwrap())
+                                                    .create();
+
+  let client = Client::new();
+  let api = mk_api(pretend_static(&client));
+
+  let res = api.patch("P5678", "updated", &[]);
+
+  moq.assert();
+
+  assert_eq!(res.unwrap().id, "P5678")
+}
@@ -0,0 +1,57 @@
+use mergebot::notify::{webhook, Note};
+use mockito::{mock, Matcher as Match};
+use reqwest::blocking::Client;
+
+fn note() -> Note {
+  Note { subject: "Deploy of my_app to prod finished".into(),
+         text: "my_app was deployed to prod".into() }
+}
+
+#[test]
+pub fn teams() {
+  let body_expected = serde_json::json!({
+    "@type": "MessageCard",
+    "title": "Deploy of my_app to prod finished",
+    "text": "my_app was deployed to prod",
+  });
+
+  let moq = mock("POST", "/teams").match_body(Match::PartialJson(body_expected))
+                                  .with_status(200)
+                                  .create();
+
+  let res = webhook::teams(&Client::new(), &format!("{}/teams", mockito::server_url()), &note());
+
+  moq.assert();
+
+  assert!(res.is_ok())
+}
+
+#[test]
+pub fn mattermost() {
+  let body_expected = serde_json::json!({
+    "text": "**Deploy of my_app to prod finished**\nmy_app was deployed to prod",
+  });
+
+  let moq = mock("POST", "/mattermost").match_body(Match::Json(body_expected))
+                                       .with_status(200)
+                                       .create();
+
+  let res = webhook::mattermost(&Client::new(),
+                                &format!("{}/mattermost", mockito::server_url()),
+                                &note());
+
+  moq.assert();
+
+  assert!(res.is_ok())
+}
+
+#[test]
+pub fn webhook_error_status() {
+  let moq = mock("POST", "/gone").with_status(404).create();
+
+  let res = webhook::mattermost(&Client::new(), &format!("{}/gone", mockito::server_url()), &note());
+
+  moq.assert();
+
+  assert!(matches!(res, Err(mergebot::notify::Error::Http(_))))
+}
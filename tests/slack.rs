@@ -13,16 +13,17 @@ fn pretend_static<T>(t: &T) -> &'static T {
 struct TokensFake;
 
 impl slack::tokens::TokenMgr for TokensFake {
-  fn tokens(&self) -> Vec<AccessRep> {
-    lock_discard_poison(&TOKENS).clone()
+  fn tokens(&self) -> slack::tokens::Result<Vec<AccessRep>> {
+    Ok(lock_discard_poison(&TOKENS).clone())
   }
-  fn set_tokens(&self, reps: Vec<AccessRep>) {
+  fn set_tokens(&self, reps: Vec<AccessRep>) -> slack::tokens::Result<()> {
     *lock_discard_poison(&TOKENS) = reps;
+    Ok(())
   }
 }
 
 lazy_static::lazy_static! {
-  static ref TOKENS: Mutex<Vec<AccessRep>> = Mutex::new(vec![AccessRep {access_token: "xoxb".into(), scope: "".into(), bot_user_id: "".into(), team: slack::access::Team {id: "team_id".into()}}]);
+  static ref TOKENS: Mutex<Vec<AccessRep>> = Mutex::new(vec![AccessRep {access_token: "xoxb".into(), scope: "".into(), bot_user_id: "".into(), team: slack::access::Team {id: "team_id".into(), name: "".into()}}]);
   static ref TOKENS_FAKE: TokensFake = TokensFake;
 }
 
@@ -60,6 +61,57 @@ pub fn groups() {
   assert_eq!(res.unwrap(), vec!["user_a".to_string(), "user_b".to_string()])
 }
 
+#[test]
+pub fn messages_upload_snippet() {
+  use slack::msg::Messages;
+
+  let url_rep = serde_json::json!({
+    "ok": true,
+    "upload_url": format!("{}/upload/F123", mockito::server_url()),
+    "file_id": "F123",
+  });
+
+  let complete_expected = serde_json::json!({
+    "files": [{"id": "F123", "title": "Changelog"}],
+    "channel_id": "C1234",
+    "thread_ts": "1503435956.000247",
+  });
+
+  let get_url = mock("POST", "/api/files.getUploadURLExternal").match_header("authorization",
+                                                                             Match::Exact("Bearer xoxb".into()))
+                                                               .match_body("filename=Changelog.txt&length=5")
+                                                               .with_status(200)
+                                                               .with_header("Content-Type", "application/json")
+                                                               .with_body(serde_json::to_string(&url_rep).unwrap())
+                                                               .create();
+
+  let upload = mock("POST", "/upload/F123").match_body("hello")
+                                           .with_status(200)
+                                           .create();
+
+  let complete = mock("POST", "/api/files.completeUploadExternal").match_header("authorization",
+                                                                                Match::Exact("Bearer xoxb".into()))
+                                                                  .match_body(Match::Json(complete_expected))
+                                                                  .with_status(200)
+                                                                  .with_header("Content-Type", "application/json")
+                                                                  .with_body(r#"{"ok": true, "files": []}"#)
+                                                                  .create();
+
+  let client = Client::new();
+  let client_ref = &client;
+  let api = mk_api(pretend_static(client_ref));
+
+  let thread = slack::msg::Id { channel: "C1234".into(),
+                                ts: "1503435956.000247".into() };
+  let res = api.upload_snippet("team_id", &thread, "Changelog", "hello");
+
+  get_url.assert();
+  upload.assert();
+  complete.assert();
+
+  assert!(res.is_ok(), "{:?}", res);
+}
+
 #[test]
 pub fn messages_send() {
   use slack::msg::Messages;
@@ -211,8 +263,40 @@ pub fn oauth_access() {
 
   moq.assert();
 
-  assert_eq!(res.unwrap().access_token, token);
-  assert_eq!(TOKENS_FAKE.get("team_id_2"), Some(String::from(token)));
+  let res = res.unwrap();
+  assert_eq!(res.access_token, token);
+  assert_eq!(res.team.name, "Slack Softball Team");
+  assert_eq!(TOKENS_FAKE.get("team_id_2").unwrap(), Some(String::from(token)));
+}
+
+#[test]
+pub fn oauth_access_error() {
+  use access::Access;
+  use slack::access;
+
+  let rep = serde_json::json!({
+      "ok": false,
+      "error": "invalid_code"
+  });
+
+  let moq = mock("POST", "/api/oauth.v2.access").match_query(Match::UrlEncoded("code".into(), "BAD_CODE".into()))
+                                                .with_status(200)
+                                                .with_header("Content-Type", "application/json")
+                                                .with_body(serde_json::to_string(&rep).unwrap())
+                                                .create();
+
+  let client = Client::new();
+  let client_ref = &client;
+  let api = mk_api(pretend_static(client_ref));
+
+  let res = api.access("BAD_CODE", "CLIENT_ID", "CLIENT_SECRET");
+
+  moq.assert();
+
+  match res {
+    | Err(slack::Error::Slack(e)) => assert_eq!(e, "invalid_code"),
+    | other => panic!("expected slack error, got {:?}", other),
+  }
 }
 
 #[test]
@@ -227,3 +311,131 @@ pub fn authentic() {
                                              http::HeaderValue::from_str(timestamp).unwrap(),
                                              http::HeaderValue::from_str(inbound_sig).unwrap()));
 }
+
+#[test]
+pub fn respond() {
+  use slack::respond::{Respond, Response};
+
+  let body_expected = serde_json::json!({
+    "response_type": "ephemeral",
+    "text": "hello",
+  });
+
+  let moq = mock("POST", "/commands/T1234/5678/abc").match_body(Match::PartialJson(body_expected))
+                                                    .with_status(200)
+                                                    .create();
+
+  let client = Client::new();
+  let client_ref = &client;
+  let api = mk_api(pretend_static(client_ref));
+
+  let res = api.respond(&format!("{}/commands/T1234/5678/abc", mockito::server_url()),
+                        &Response::ephemeral("hello"));
+
+  moq.assert();
+
+  assert!(res.is_ok())
+}
+
+#[test]
+pub fn groups_async() {
+  use slack::nonblocking::GroupsAsync;
+
+  let rep = serde_json::json!({
+    "ok": true,
+    "users": ["user_a"]
+  });
+
+  let moq = mock("GET", "/api/usergroups.users.list?usergroup=DEF456").match_header("authorization",
+                                                                                    Match::Exact("Bearer xoxb".into()))
+                                                                      .with_status(200)
+                                                                      .with_header("Content-Type", "application/json")
+                                                                      .with_body(serde_json::to_string(&rep).unwrap())
+                                                                      .create();
+
+  let client = Client::new();
+  let api = mk_api(pretend_static(&client));
+  let api = pretend_static(&api);
+
+  let rt = tokio::runtime::Runtime::new().unwrap();
+  let res = rt.block_on(api.contains_user_async("team_id", "DEF456", "user_a"));
+
+  moq.assert();
+
+  assert!(res.unwrap());
+}
+
+#[test]
+pub fn groups_rate_limited() {
+  use slack::groups::Groups;
+
+  let moq = mock("GET", "/api/usergroups.users.list?usergroup=GHI789").with_status(429)
+                                                                      .with_header("Retry-After", "0")
+                                                                      .expect(4)
+                                                                      .create();
+
+  let client = Client::new();
+  let api = mk_api(pretend_static(&client));
+
+  let res = api.expand("team_id", "GHI789");
+
+  moq.assert();
+
+  assert!(matches!(res, Err(slack::Error::RateLimited(wait)) if wait.as_secs() == 0));
+}
+
+#[test]
+pub fn groups_rate_limited_for_long() {
+  use slack::groups::Groups;
+
+  let moq = mock("GET", "/api/usergroups.users.list?usergroup=JKL012").with_status(429)
+                                                                      .with_header("Retry-After", "3600")
+                                                                      .expect(1)
+                                                                      .create();
+
+  let client = Client::new();
+  let api = mk_api(pretend_static(&client));
+
+  let res = api.expand("team_id", "JKL012");
+
+  moq.assert();
+
+  assert!(matches!(res, Err(slack::Error::RateLimited(wait)) if wait.as_secs() == 3600));
+}
+
+#[test]
+pub fn messages_update() {
+  use slack::msg::Messages;
+
+  let body_expected = serde_json::json!({
+    "channel": "C1234",
+    "ts": "1503435956.000247",
+    "blocks": [],
+  });
+
+  let rep = serde_json::json!({
+    "ok": true,
+    "channel": "C1234",
+    "ts": "1503435956.000247",
+    "text": "Updated text you carefully authored"
+  });
+
+  let moq = mock("POST", "/api/chat.update").match_header("authorization", Match::Exact("Bearer xoxb".into()))
+                                            .match_body(Match::Json(body_expected))
+                                            .with_status(200)
+                                            .with_header("Content-Type", "application/json")
+                                            .with_body(serde_json::to_string(&rep).unwrap())
+                                            .create();
+
+  let client = Client::new();
+  let api = mk_api(pretend_static(&client));
+
+  let id = slack::msg::Id { ts: "1503435956.000247".to_string(),
+                            channel: "C1234".to_string() };
+
+  let res = api.update("team_id", &id, &[]);
+
+  moq.assert();
+
+  assert_eq!(res.unwrap().id, id)
+}